@@ -26,7 +26,6 @@ fn main() {
     // TODO: Add support for command line arguments
     // Print available ports
 
-    // TODO: Add custom errors ? AT_BUSY_ERROR
     // TODO: Add function to check if ATE is on
 
     let path = get_connected_port();
@@ -52,10 +51,12 @@ fn main() {
 
     // Two timer instances
     let atat_timer = timer::SysTimer::new();
+    let mut radio_timer = timer::SysTimer::new();
 
     // Atat client
     let config = atat::Config::new(atat::Mode::Blocking);
-    let digester = atat::AtDigester::<rui3_at::at::urc::URCMessages>::new();
+    let digester = atat::AtDigester::<rui3_at::at::urc::URCMessages>::new()
+        .with_custom_error(rui3_at::error::custom_error);
     let (client, mut ingress) =
         atat::ClientBuilder::<_, _, _, TIMER_HZ, ATAT_RX_SIZE, RES_CAPACITY, URC_CAPACITY>::new(
             serial_tx, atat_timer, digester, config,
@@ -135,16 +136,19 @@ fn main() {
 
         // Configure radio
         println!("Configuring radio");
-        match radio.configure(Configuration::default()) {
+        match radio.configure(Configuration::default(), &mut radio_timer) {
             Ok(_) => println!("Configuration successful"),
             Err(e) => println!("Configuration failed: {:?}", e),
         }
 
         // Receive data
         loop {
-            match radio.receive() {
-                Ok(data) => {
-                    println!("[RADIO THREAD] - Received data: {:?}", data);
+            match radio.receive(&mut radio_timer) {
+                Ok(packet) => {
+                    println!(
+                        "[RADIO THREAD] - Received data: {:?} (RSSI: {}, SNR: {}, at {})",
+                        packet.payload, packet.rssi, packet.snr, packet.timestamp
+                    );
                 }
                 Err(e) => {
                     println!("[RADIO THREAD] - Error while receiving data: {:?}", e);
@@ -52,7 +52,8 @@ fn main() {
 
     // Atat client
     let config = atat::Config::new(atat::Mode::Blocking);
-    let digester = atat::AtDigester::<rui3_at::at::urc::URCMessages>::new();
+    let digester = atat::AtDigester::<rui3_at::at::urc::URCMessages>::new()
+        .with_custom_error(rui3_at::error::custom_error);
     let (client, mut ingress) =
         atat::ClientBuilder::<_, _, _, TIMER_HZ, ATAT_RX_SIZE, RES_CAPACITY, URC_CAPACITY>::new(
             serial_tx, atat_timer, digester, config,
@@ -129,11 +130,12 @@ fn main() {
     thread::spawn(move || {
         // Create Rui3Radio instance
         let mut radio = rui3_at::Rui3Radio::new(client);
+        let mut radio_timer = timer::SysTimer::new();
         println!("[RADIO THREAD] - Radio created");
 
         // Configure radio
         println!("Configuring radio");
-        match radio.configure(Configuration::default()) {
+        match radio.configure(Configuration::default(), &mut radio_timer) {
             Ok(_) => println!("Configuration successful"),
             Err(e) => println!("Configuration failed: {:?}", e),
         }
@@ -141,7 +143,7 @@ fn main() {
         // Send data
         loop {
             println!("[RADIO THREAD] - Sending data");
-            match radio.send(&[88]) {
+            match radio.send(&[88], &mut radio_timer) {
                 Ok(_) => println!("[RADIO THREAD] - Data sent"),
                 Err(e) => println!("Data not sent: {:?}", e),
             }
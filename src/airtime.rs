@@ -0,0 +1,169 @@
+//! Time on air of a packet sent with a given [`Configuration`].
+
+use crate::{
+    at::commands::p2p::{Bandwidth, CodeRate, WorkingMode},
+    Configuration,
+};
+
+/// Bytes RUI3 adds around an FSK payload: 3 bytes of sync word, 1 byte of length and
+/// 2 bytes of CRC.
+const FSK_OVERHEAD: u64 = 3 + 1 + 2;
+
+impl Configuration {
+    /// Returns how long a packet of `payload_len` bytes occupies the channel.
+    ///
+    /// `LoRa` packets follow the formula of the `SX126x` datasheet with an explicit
+    /// header and CRC, as sent by RUI3. Low data rate optimisation is enabled when a
+    /// symbol lasts 16 ms or more, like the module does.
+    ///
+    /// FSK packets, in [`WorkingMode::FskP2P`] or with an FSK bandwidth, are sent at
+    /// `fsk_bitrate` with a preamble of `preamble_length` bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload_len` - The length of the payload in bytes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let configuration = rui3_at::Configuration::default();
+    /// // SF7, 125 kHz, 4/5, 8 symbols of preamble.
+    /// assert_eq!(configuration.time_on_air(10).to_micros(), 41_216);
+    /// ```
+    #[must_use]
+    pub fn time_on_air(&self, payload_len: usize) -> fugit::MicrosDurationU32 {
+        // Payloads are at most 255 bytes, saturate instead of overflowing.
+        let payload_len = u16::try_from(payload_len).unwrap_or(u16::MAX);
+
+        let micros = match (&self.working_mode, &self.bandwidth) {
+            (WorkingMode::FskP2P, _) | (_, Bandwidth::Fsk(_)) => self.fsk_micros(payload_len),
+            (_, bandwidth) => self.lora_micros(bandwidth, payload_len),
+        };
+        fugit::MicrosDurationU32::micros(u32::try_from(micros).unwrap_or(u32::MAX))
+    }
+
+    /// Time on air of an FSK packet, in microseconds.
+    fn fsk_micros(&self, payload_len: u16) -> u64 {
+        let bytes = u64::from(self.preamble_length) + FSK_OVERHEAD + u64::from(payload_len);
+        (bytes * 8 * 1_000_000).div_ceil(u64::from(self.fsk_bitrate.max(1)))
+    }
+
+    /// Time on air of a `LoRa` packet, in microseconds.
+    fn lora_micros(&self, bandwidth: &Bandwidth, payload_len: u16) -> u64 {
+        // Every LoRa bandwidth is 500 kHz divided by a power of two or by 3 times one.
+        let divider: u64 = match bandwidth {
+            Bandwidth::LoRa500KHz => 1,
+            Bandwidth::LoRa250KHz => 2,
+            Bandwidth::LoRa125KHz => 4,
+            Bandwidth::LoRa62_5MHz => 8,
+            Bandwidth::LoRa41_67MHz => 12,
+            Bandwidth::LoRa31_25MHz => 16,
+            Bandwidth::LoRa20_83MHz => 24,
+            Bandwidth::LoRa15_63MHz => 32,
+            Bandwidth::LoRa10_4MHz => 48,
+            Bandwidth::LoRa7_8MHz | Bandwidth::Fsk(_) => 64,
+        };
+        let code_rate: i64 = match self.code_rate {
+            CodeRate::PCR4_5 => 1,
+            CodeRate::PCR4_6 => 2,
+            CodeRate::PCR4_7 => 3,
+            CodeRate::PCR4_8 => 4,
+        };
+        let spreading_factor = i64::from(self.spreading_factor.clamp(5, 12));
+
+        // A symbol lasts 2^SF / BW seconds, i.e. 2^SF * 2 * divider microseconds.
+        let symbol_us = (1_u64 << spreading_factor) * 2 * divider;
+        let low_data_rate = i64::from(symbol_us >= 16_000);
+
+        // Symbol counts are kept in quarters, as the preamble adds 4.25 or 6.25 symbols.
+        let (preamble_quarters, header_bits) = if spreading_factor < 7 {
+            (25, 20)
+        } else {
+            (17, 28)
+        };
+        let bits = 8 * i64::from(payload_len) + 16 - 4 * spreading_factor + header_bits;
+        let bits_per_block = 4 * (spreading_factor - 2 * low_data_rate);
+        let blocks = if bits > 0 {
+            (bits + bits_per_block - 1) / bits_per_block
+        } else {
+            0
+        };
+        let payload_symbols = 8 + blocks * (code_rate + 4);
+
+        let quarters =
+            4 * i64::from(self.preamble_length) + preamble_quarters + 4 * payload_symbols;
+        u64::try_from(quarters).unwrap_or(0) * symbol_us / 4
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    fn lora(spreading_factor: u8, bandwidth: Bandwidth, code_rate: CodeRate) -> Configuration {
+        Configuration {
+            spreading_factor,
+            bandwidth,
+            code_rate,
+            ..Configuration::default()
+        }
+    }
+
+    /// Expected values from the Semtech `LoRa` calculator, with an explicit header,
+    /// CRC and 8 symbols of preamble.
+    #[test]
+    fn lora_time_on_air() {
+        let cases = [
+            (7, Bandwidth::LoRa125KHz, CodeRate::PCR4_5, 0, 25_856),
+            (7, Bandwidth::LoRa125KHz, CodeRate::PCR4_5, 10, 41_216),
+            (7, Bandwidth::LoRa125KHz, CodeRate::PCR4_5, 51, 102_656),
+            (7, Bandwidth::LoRa250KHz, CodeRate::PCR4_5, 10, 20_608),
+            (7, Bandwidth::LoRa500KHz, CodeRate::PCR4_5, 255, 99_904),
+            (9, Bandwidth::LoRa125KHz, CodeRate::PCR4_5, 20, 185_344),
+            (10, Bandwidth::LoRa125KHz, CodeRate::PCR4_8, 32, 624_640),
+            (12, Bandwidth::LoRa125KHz, CodeRate::PCR4_5, 10, 991_232),
+            (12, Bandwidth::LoRa125KHz, CodeRate::PCR4_5, 51, 2_465_792),
+            (6, Bandwidth::LoRa500KHz, CodeRate::PCR4_5, 10, 5_408),
+        ];
+
+        for (spreading_factor, bandwidth, code_rate, payload_len, micros) in cases {
+            let description = format!("SF{spreading_factor} {bandwidth:?} {code_rate:?}");
+            let configuration = lora(spreading_factor, bandwidth, code_rate);
+            assert_eq!(
+                configuration.time_on_air(payload_len).to_micros(),
+                micros,
+                "{description}, {payload_len} bytes",
+            );
+        }
+    }
+
+    #[test]
+    fn low_data_rate_optimisation() {
+        // SF11 at 125 kHz has 16.384 ms symbols and needs the optimisation.
+        let optimised = lora(11, Bandwidth::LoRa125KHz, CodeRate::PCR4_5);
+        assert_eq!(optimised.time_on_air(10).to_micros(), 577_536);
+
+        // The same packet at 250 kHz has 8.192 ms symbols and does not.
+        let regular = lora(11, Bandwidth::LoRa250KHz, CodeRate::PCR4_5);
+        assert_eq!(regular.time_on_air(10).to_micros(), 247_808);
+    }
+
+    #[test]
+    fn fsk_time_on_air() {
+        let configuration = Configuration {
+            working_mode: WorkingMode::FskP2P,
+            bandwidth: Bandwidth::Fsk(100_000),
+            fsk_bitrate: 50_000,
+            ..Configuration::default()
+        };
+        // (8 + 3 + 1 + 10 + 2) bytes at 50 kbit/s.
+        assert_eq!(configuration.time_on_air(10).to_micros(), 3_840);
+
+        let configuration = Configuration {
+            fsk_bitrate: 4_800,
+            ..configuration
+        };
+        assert_eq!(configuration.time_on_air(0).to_micros(), 23_334);
+    }
+}
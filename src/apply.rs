@@ -0,0 +1,294 @@
+//! Applying a [`Configuration`] to the module, shared by [`Rui3Radio`] and the async
+//! client.
+//!
+//! [`ConfigurationPlan`] decides which commands set the differing parameters and in
+//! which order, and each client sends them with its own AT client.
+//!
+//! [`Rui3Radio`]: crate::Rui3Radio
+
+use core::fmt::Write;
+
+use crate::{
+    at,
+    at::{commands::p2p::WorkingMode, responses::NoResponse},
+    Configuration, ConfigurationChanges, ConfigurationParameter, Rui3Error,
+};
+
+/// Longest command setting parameters, `AT+P2P` with every field at its maximum.
+const PARAMETER_COMMAND_LEN: usize = 64;
+
+/// The modulation parameters set at once by `AT+P2P`.
+const MODULATION: [ConfigurationParameter; 6] = [
+    ConfigurationParameter::Frequency,
+    ConfigurationParameter::SpreadingFactor,
+    ConfigurationParameter::Bandwidth,
+    ConfigurationParameter::CodeRate,
+    ConfigurationParameter::PreambleLength,
+    ConfigurationParameter::TxPower,
+];
+
+/// A command setting parameters of a [`Configuration`], encoded once for both
+/// clients.
+#[derive(Clone)]
+pub struct ParameterCommand {
+    bytes: atat::heapless::Vec<u8, PARAMETER_COMMAND_LEN>,
+}
+
+impl ParameterCommand {
+    /// Encodes a setter answered with a plain `OK`.
+    fn new<A, const LEN: usize>(command: &A) -> Self
+    where
+        A: atat::AtatCmd<LEN, Response = NoResponse>,
+    {
+        // Every setter is shorter than `AT+P2P`.
+        let bytes = atat::heapless::Vec::from_slice(&command.as_bytes()).unwrap_or_default();
+        Self { bytes }
+    }
+}
+
+impl atat::AtatCmd<PARAMETER_COMMAND_LEN> for ParameterCommand {
+    type Response = NoResponse;
+
+    fn as_bytes(&self) -> atat::heapless::Vec<u8, PARAMETER_COMMAND_LEN> {
+        self.bytes.clone()
+    }
+
+    fn parse(
+        &self,
+        resp: Result<&[u8], atat::InternalError>,
+    ) -> Result<Self::Response, atat::Error> {
+        resp.map(|_| NoResponse {}).map_err(atat::Error::from)
+    }
+}
+
+/// What a client does next to apply a configuration.
+pub enum ApplyStep {
+    /// Send the command, then report the answer with [`ConfigurationPlan::applied`]
+    /// or [`ConfigurationPlan::falls_back`].
+    Send(ParameterCommand),
+    /// Wait for the module to answer again, as it restarts after a change of
+    /// working mode.
+    WaitForRestart,
+}
+
+/// The commands setting the differing parameters of a configuration, in order.
+///
+/// The working mode comes first, followed by a restart. In `LoRa` P2P mode the
+/// modulation parameters are set at once with `AT+P2P` if the firmware knows it,
+/// then the encryption and the parameters which must match between peers.
+pub struct ConfigurationPlan<'a> {
+    new: &'a Configuration,
+    /// Parameters not set yet, in order.
+    remaining: ConfigurationChanges,
+    /// Parameters set by the last command sent.
+    sent: ConfigurationChanges,
+    /// Whether the last command sent is `AT+P2P`.
+    sent_combined: bool,
+    /// Parameters set so far.
+    changes: ConfigurationChanges,
+    /// Whether the firmware may know `AT+P2P`.
+    combined_p2p: bool,
+    /// Whether the module restarts after the last command sent.
+    restarting: bool,
+}
+
+impl<'a> ConfigurationPlan<'a> {
+    /// Plans setting the `differing` parameters of `new`.
+    ///
+    /// `combined_p2p` is `false` once the firmware rejected `AT+P2P`.
+    pub const fn new(
+        new: &'a Configuration,
+        differing: ConfigurationChanges,
+        combined_p2p: bool,
+    ) -> Self {
+        Self {
+            new,
+            remaining: differing,
+            sent: ConfigurationChanges::new(),
+            sent_combined: false,
+            changes: ConfigurationChanges::new(),
+            combined_p2p,
+            restarting: false,
+        }
+    }
+
+    /// Returns the next step, or `None` once every parameter is set.
+    pub fn next_step(&mut self) -> Option<ApplyStep> {
+        if core::mem::take(&mut self.restarting) {
+            return Some(ApplyStep::WaitForRestart);
+        }
+        let parameter = *self.remaining.first()?;
+        self.sent.clear();
+        self.sent_combined = self.combined_p2p
+            && self.new.working_mode == WorkingMode::LoRaP2P
+            && MODULATION.contains(&parameter);
+
+        if self.sent_combined {
+            log::debug!("Setting the modulation parameters");
+            for parameter in self.remaining.iter().filter(|p| MODULATION.contains(p)) {
+                // There is room for every parameter.
+                let _ = self.sent.push(*parameter);
+            }
+            return Some(ApplyStep::Send(ParameterCommand::new(
+                &at::commands::p2p::SetP2P {
+                    frequency: self.new.frequency,
+                    spreading_factor: self.new.spreading_factor,
+                    bandwidth: self.new.bandwidth.clone(),
+                    code_rate: self.new.code_rate.clone(),
+                    preamble_length: self.new.preamble_length,
+                    tx_power: self.new.tx_power,
+                },
+            )));
+        }
+
+        log::debug!("Setting {parameter:?}");
+        // There is room for every parameter.
+        let _ = self.sent.push(parameter);
+        Some(ApplyStep::Send(self.command(parameter)))
+    }
+
+    /// Records that the module accepted the last command, and returns the
+    /// parameters it set.
+    pub fn applied(&mut self) -> &[ConfigurationParameter] {
+        self.remaining
+            .retain(|parameter| !self.sent.contains(parameter));
+        for parameter in &self.sent {
+            // There is room for every parameter.
+            let _ = self.changes.push(*parameter);
+        }
+        self.restarting = self.sent.contains(&ConfigurationParameter::WorkingMode);
+        &self.sent
+    }
+
+    /// Returns `true` if the plan goes on without the last command after `error`.
+    ///
+    /// Firmware which does not know `AT+P2P` gets one command per parameter
+    /// instead.
+    pub fn falls_back(&mut self, error: &Rui3Error) -> bool {
+        if *error == Rui3Error::CommandNotFound && self.sent_combined {
+            log::debug!("AT+P2P not supported, setting parameters one by one");
+            self.combined_p2p = false;
+            return true;
+        }
+        false
+    }
+
+    /// Returns whether the firmware may know `AT+P2P`, to remember for the next
+    /// configuration.
+    pub const fn combined_p2p(&self) -> bool {
+        self.combined_p2p
+    }
+
+    /// Returns the parameters set, in order.
+    pub fn into_changes(self) -> ConfigurationChanges {
+        self.changes
+    }
+
+    /// Returns the command setting a parameter on its own.
+    fn command(&self, parameter: ConfigurationParameter) -> ParameterCommand {
+        let new = self.new;
+        match parameter {
+            ConfigurationParameter::WorkingMode => {
+                ParameterCommand::new(&at::commands::p2p::SetNetworkWorkingMode {
+                    mode: new.working_mode.clone(),
+                })
+            }
+            ConfigurationParameter::Frequency => {
+                ParameterCommand::new(&at::commands::p2p::SetP2PFrequency {
+                    frequency: new.frequency,
+                })
+            }
+            ConfigurationParameter::SpreadingFactor => {
+                ParameterCommand::new(&at::commands::p2p::SetP2PSpreadingFactor {
+                    spreading_factor: new.spreading_factor,
+                })
+            }
+            ConfigurationParameter::Bandwidth => {
+                ParameterCommand::new(&at::commands::p2p::SetP2PBandwidth {
+                    bandwidth: new.bandwidth.clone(),
+                })
+            }
+            ConfigurationParameter::CodeRate => {
+                ParameterCommand::new(&at::commands::p2p::SetCodeRate {
+                    code_rate: new.code_rate.clone(),
+                })
+            }
+            ConfigurationParameter::PreambleLength => {
+                ParameterCommand::new(&at::commands::p2p::SetPreambleLength {
+                    preamble_length: new.preamble_length,
+                })
+            }
+            ConfigurationParameter::TxPower => {
+                ParameterCommand::new(&at::commands::p2p::SetTxPower {
+                    tx_power: new.tx_power,
+                })
+            }
+            ConfigurationParameter::FskBitrate => {
+                ParameterCommand::new(&at::commands::p2p::SetFskBitrate {
+                    bitrate: new.fsk_bitrate,
+                })
+            }
+            ConfigurationParameter::FskDeviation => {
+                ParameterCommand::new(&at::commands::p2p::SetFskDeviation {
+                    deviation: new.fsk_deviation,
+                })
+            }
+            ConfigurationParameter::EncryptionKey => {
+                ParameterCommand::new(&at::commands::p2p::SetEncryptionKey {
+                    encryption_key: new.encryption_key.clone(),
+                })
+            }
+            ConfigurationParameter::Encryption => {
+                ParameterCommand::new(&at::commands::p2p::SetEncryptionMode {
+                    encryption: new.encrypted.clone(),
+                })
+            }
+            ConfigurationParameter::SyncWord => {
+                let mut sync_word = atat::heapless::String::new();
+                // Four hex digits always fit.
+                let _ = write!(sync_word, "{:04X}", new.sync_word);
+                ParameterCommand::new(&at::commands::p2p::SetSyncWord { sync_word })
+            }
+            ConfigurationParameter::IqInversion => {
+                ParameterCommand::new(&at::commands::p2p::SetIqInversion {
+                    iq_inversion: new.iq_inversion.into(),
+                })
+            }
+            ConfigurationParameter::SymbolTimeout => {
+                ParameterCommand::new(&at::commands::p2p::SetSymbolTimeout {
+                    symbol_timeout: new.symbol_timeout,
+                })
+            }
+        }
+    }
+}
+
+impl Configuration {
+    /// Copies a parameter from `other`, once it has been applied to the module.
+    pub(crate) fn copy_parameter(&mut self, other: &Self, parameter: ConfigurationParameter) {
+        match parameter {
+            ConfigurationParameter::WorkingMode => {
+                self.working_mode = other.working_mode.clone();
+            }
+            ConfigurationParameter::Frequency => self.frequency = other.frequency,
+            ConfigurationParameter::SpreadingFactor => {
+                self.spreading_factor = other.spreading_factor;
+            }
+            ConfigurationParameter::Bandwidth => self.bandwidth = other.bandwidth.clone(),
+            ConfigurationParameter::CodeRate => self.code_rate = other.code_rate.clone(),
+            ConfigurationParameter::PreambleLength => {
+                self.preamble_length = other.preamble_length;
+            }
+            ConfigurationParameter::TxPower => self.tx_power = other.tx_power,
+            ConfigurationParameter::FskBitrate => self.fsk_bitrate = other.fsk_bitrate,
+            ConfigurationParameter::FskDeviation => self.fsk_deviation = other.fsk_deviation,
+            ConfigurationParameter::EncryptionKey => {
+                self.encryption_key = other.encryption_key.clone();
+            }
+            ConfigurationParameter::Encryption => self.encrypted = other.encrypted.clone(),
+            ConfigurationParameter::SyncWord => self.sync_word = other.sync_word,
+            ConfigurationParameter::IqInversion => self.iq_inversion = other.iq_inversion,
+            ConfigurationParameter::SymbolTimeout => self.symbol_timeout = other.symbol_timeout,
+        }
+    }
+}
@@ -0,0 +1,684 @@
+//! Async radio client, for executors such as Embassy.
+//!
+//! `atat` 0.18 only ships a blocking client, so the async client, the URC
+//! subscription and the timer are described by the [`AsyncClient`],
+//! [`UrcSubscription`] and [`Timer`] traits. Implement them on top of the AT client
+//! of your executor, e.g. the async client of newer `atat` releases, an
+//! `embassy-sync` subscriber fed by its URC channel and `embassy-time`.
+//!
+//! Commands and responses are the ones of [`crate::at`], shared with [`Rui3Radio`].
+//!
+//! [`Rui3Radio`]: crate::Rui3Radio
+
+// Embedded executors poll futures on a single thread, they need not be `Send`.
+#![allow(clippy::future_not_send)]
+
+use core::{
+    future::{poll_fn, Future},
+    pin::pin,
+    task::Poll,
+};
+
+use crate::{
+    apply::{ApplyStep, ConfigurationPlan},
+    at,
+    at::commands::{Checked, CheckedResponse},
+    boot_poll_delays, encode_hex, Configuration, ConfigurationChanges, ReceivedPacket, Rui3Error,
+};
+
+/// An AT client that sends commands asynchronously.
+///
+/// Named apart from the blocking [`atat::AtatClient`], which [`Rui3Radio`] uses.
+///
+/// [`Rui3Radio`]: crate::Rui3Radio
+#[allow(async_fn_in_trait)]
+pub trait AsyncClient {
+    /// Sends a command and waits for its response.
+    async fn send<A, const LEN: usize>(&mut self, command: &A) -> Result<A::Response, atat::Error>
+    where
+        A: atat::AtatCmd<LEN>;
+}
+
+/// A subscription to the URCs sent by the module.
+#[allow(async_fn_in_trait)]
+pub trait UrcSubscription {
+    /// Waits for the next URC.
+    async fn next_urc(&mut self) -> at::urc::URCMessages;
+}
+
+/// A timer bounding the waits for the module.
+#[allow(async_fn_in_trait)]
+pub trait Timer {
+    /// Completes once `duration` has elapsed.
+    async fn after(&mut self, duration: fugit::MicrosDurationU32);
+}
+
+/// A struct to define the async radio client.
+pub struct AsyncRui3Radio<C, S>
+where
+    C: AsyncClient,
+    S: UrcSubscription,
+{
+    client: C,
+    urcs: S,
+    /// Last configuration applied to the module.
+    configuration: Configuration,
+    /// Whether `configuration` matches the module.
+    configuration_known: bool,
+    /// Whether the firmware may know `AT+P2P`.
+    combined_p2p: bool,
+    /// Time allowed on top of the time on air for the end of a transmission.
+    tx_timeout_margin: fugit::MillisDurationU32,
+}
+
+impl<C, S> AsyncRui3Radio<C, S>
+where
+    C: AsyncClient,
+    S: UrcSubscription,
+{
+    /// Creates a new async radio client.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The async AT client.
+    /// * `urcs` - The subscription to the URCs of the module.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// let radio_client = AsyncRui3Radio::new(client, urc_channel.subscribe().unwrap());
+    /// ```
+    pub fn new(client: C, urcs: S) -> Self {
+        Self {
+            client,
+            urcs,
+            configuration: Configuration::default(),
+            configuration_known: false,
+            combined_p2p: true,
+            tx_timeout_margin: fugit::MillisDurationU32::millis(500),
+        }
+    }
+
+    /// Converts data to hex and sends it.
+    ///
+    /// Temporarily disables RX, sends the data and waits for `+EVT:TXP2P DONE`.
+    /// Finally re-enables RX.
+    ///
+    /// Gives up if `+EVT:TXP2P DONE` is not received within the time on air of the
+    /// packet plus the margin set with [`set_tx_timeout_margin`], like
+    /// [`Rui3Radio::send`].
+    ///
+    /// [`set_tx_timeout_margin`]: #method.set_tx_timeout_margin
+    /// [`Rui3Radio::send`]: crate::Rui3Radio::send
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to send.
+    /// * `timer` - The timer used to measure the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// radio_client.send(&data, &mut timer).await?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::TxTimeout`] if the transmission is not reported in time,
+    /// [`Rui3Error::ParamError`] if `data` is too long, [`Rui3Error::ChannelBusy`] if
+    /// channel activity detection is enabled and the channel is busy, or another
+    /// [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub async fn send<T>(&mut self, data: &[u8], timer: &mut T) -> Result<(), Rui3Error>
+    where
+        T: Timer,
+    {
+        // Convert each byte of data to a hex string.
+        let mut payload: atat::heapless::String<500> =
+            encode_hex(data).ok_or(Rui3Error::ParamError)?;
+        // Add \r\n to the end of the string.
+        payload
+            .push_str("\r\n")
+            .map_err(|()| Rui3Error::ParamError)?;
+
+        // Disable RX.
+        self.send_command(&at::commands::p2p::ReceiveData {
+            window: at::commands::p2p::ReceiveWindow::StopListening,
+        })
+        .await?;
+
+        // Send data.
+        self.send_command(&at::commands::p2p::SendData { payload })
+            .await?;
+        log::debug!("Sending data: {data:?}");
+
+        // Wait for the module to report the end of the transmission.
+        let timeout = self.configuration.time_on_air(data.len()) + self.tx_timeout_margin.convert();
+        log::debug!("Waiting up to {timeout} for the transmission");
+        let end = with_timeout(next_tx_end(&mut self.urcs), timer.after(timeout)).await;
+        match end {
+            Some(at::urc::URCMessages::PeerToPeerTxDone) => {}
+            Some(_) => log::debug!("Channel busy, nothing sent"),
+            None => {
+                log::debug!("Transmission timed out");
+                return Err(Rui3Error::TxTimeout);
+            }
+        }
+
+        // Re-enable RX.
+        self.send_command(&at::commands::p2p::ReceiveData {
+            window: at::commands::p2p::ReceiveWindow::Continuous,
+        })
+        .await?;
+        match end {
+            Some(at::urc::URCMessages::CadDetected) => Err(Rui3Error::ChannelBusy),
+            _ => Ok(()),
+        }
+    }
+
+    /// Receives data in continuous mode.
+    ///
+    /// Restarts RX and waits for the first received packet, or until `timeout`
+    /// elapses. RX stays enabled after a timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The longest time to wait for a packet.
+    /// * `clock` - The clock used to timestamp the packet.
+    /// * `timer` - The timer used to measure the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// let timeout = fugit::MillisDurationU32::secs(5);
+    /// if let Some(packet) = radio_client.receive(timeout, &mut clock, &mut timer).await? {
+    ///     let data = &packet.payload;
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub async fn receive<CLK, T, const TIMER_HZ: u32>(
+        &mut self,
+        timeout: fugit::MillisDurationU32,
+        clock: &mut CLK,
+        timer: &mut T,
+    ) -> Result<Option<ReceivedPacket<TIMER_HZ>>, Rui3Error>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+        T: Timer,
+    {
+        self.send_command(&at::commands::p2p::ReceiveData {
+            window: at::commands::p2p::ReceiveWindow::StopListening,
+        })
+        .await?;
+
+        // Enable RX.
+        self.send_command(&at::commands::p2p::ReceiveData {
+            window: at::commands::p2p::ReceiveWindow::Continuous,
+        })
+        .await?;
+
+        let packet =
+            with_timeout(next_packet(&mut self.urcs), timer.after(timeout.convert())).await;
+        Ok(packet.map(|(rssi, snr, payload)| ReceivedPacket {
+            payload,
+            rssi,
+            snr,
+            timestamp: clock.now(),
+        }))
+    }
+
+    /// Sets client to the desired configuration.
+    ///
+    /// Sends every parameter on the first call, then only the ones which differ
+    /// from the configuration applied, like [`Rui3Radio::configure`]. An empty
+    /// encryption key is never sent.
+    ///
+    /// Changing the working mode restarts the module, the other parameters are set
+    /// once it answers again.
+    ///
+    /// [`Rui3Radio::configure`]: crate::Rui3Radio::configure
+    ///
+    /// # Arguments
+    ///
+    /// * `configuration` - The configuration to apply.
+    /// * `timer` - The timer used to wait for the module after a restart.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// let changes = radio_client.configure(Configuration::default(), &mut timer).await?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Rui3Error`] if the module rejects a command or the AT client fails,
+    /// or [`Rui3Error::NotResponding`] if it does not answer within 5 seconds after a
+    /// restart. The parameters set before the error are kept, and every parameter is
+    /// sent again on the next call.
+    pub async fn configure<T>(
+        &mut self,
+        configuration: Configuration,
+        timer: &mut T,
+    ) -> Result<ConfigurationChanges, Rui3Error>
+    where
+        T: Timer,
+    {
+        log::debug!("Config starting: {configuration:?}");
+        let differing =
+            configuration.parameters(self.configuration_known.then_some(&self.configuration));
+        let mut plan = ConfigurationPlan::new(&configuration, differing, self.combined_p2p);
+        let result = self.apply_plan(&mut plan, &configuration, timer).await;
+        self.combined_p2p = plan.combined_p2p();
+        if let Err(error) = result {
+            // The module may have applied the parameter anyway.
+            self.configuration_known = false;
+            return Err(error);
+        }
+        self.configuration_known = true;
+        let changes = plan.into_changes();
+        log::debug!("Changed {changes:?}");
+        Ok(changes)
+    }
+
+    #[allow(missing_doc_code_examples)]
+    /// Sets the time allowed on top of the time on air for the module to report the
+    /// end of a transmission.
+    ///
+    /// Defaults to 500 ms, which covers the serial link and the module latency.
+    pub const fn set_tx_timeout_margin(&mut self, margin: fugit::MillisDurationU32) {
+        self.tx_timeout_margin = margin;
+    }
+
+    /// Returns the last configuration applied to the module.
+    pub const fn configuration(&self) -> &Configuration {
+        &self.configuration
+    }
+
+    /// Sends the commands of a configuration plan, recording each parameter set.
+    async fn apply_plan<T>(
+        &mut self,
+        plan: &mut ConfigurationPlan<'_>,
+        new: &Configuration,
+        timer: &mut T,
+    ) -> Result<(), Rui3Error>
+    where
+        T: Timer,
+    {
+        while let Some(step) = plan.next_step() {
+            match step {
+                ApplyStep::Send(command) => match self.send_command(&command).await {
+                    Ok(_) => {
+                        for parameter in plan.applied() {
+                            self.configuration.copy_parameter(new, *parameter);
+                        }
+                    }
+                    Err(error) if plan.falls_back(&error) => {}
+                    Err(error) => return Err(error),
+                },
+                ApplyStep::WaitForRestart => self.wait_for_boot(timer).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends `AT` until the module answers, waiting longer after each attempt.
+    async fn wait_for_boot<T>(&mut self, timer: &mut T) -> Result<(), Rui3Error>
+    where
+        T: Timer,
+    {
+        let mut delays = boot_poll_delays();
+        loop {
+            match self
+                .send_command(&at::commands::general::Attention {})
+                .await
+            {
+                Ok(_) => return Ok(()),
+                Err(error) => log::debug!("Module not ready: {error:?}"),
+            }
+
+            let Some(delay) = delays.next() else {
+                return Err(Rui3Error::NotResponding);
+            };
+            timer.after(delay.convert()).await;
+        }
+    }
+
+    /// Sends a command, reporting RUI3 error lines as [`Rui3Error`]s.
+    async fn send_command<A, const LEN: usize>(
+        &mut self,
+        command: &A,
+    ) -> Result<A::Response, Rui3Error>
+    where
+        A: atat::AtatCmd<LEN>,
+    {
+        let CheckedResponse(response) = self.client.send(&Checked(command)).await?;
+        response
+    }
+}
+
+/// Waits for `future`, or returns `None` if `timeout` completes first.
+async fn with_timeout<F, T>(future: F, timeout: T) -> Option<F::Output>
+where
+    F: Future,
+    T: Future<Output = ()>,
+{
+    let mut future = pin!(future);
+    let mut timeout = pin!(timeout);
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        timeout.as_mut().poll(cx).map(|()| None)
+    })
+    .await
+}
+
+/// Waits for the module to report the end of a transmission, or a busy channel.
+async fn next_tx_end<S>(urcs: &mut S) -> at::urc::URCMessages
+where
+    S: UrcSubscription,
+{
+    loop {
+        let urc = urcs.next_urc().await;
+        if matches!(
+            urc,
+            at::urc::URCMessages::PeerToPeerTxDone | at::urc::URCMessages::CadDetected
+        ) {
+            return urc;
+        }
+    }
+}
+
+/// Waits for a received packet, and returns its RSSI, SNR and payload.
+async fn next_packet<S>(urcs: &mut S) -> (i16, i16, at::urc::Payload)
+where
+    S: UrcSubscription,
+{
+    loop {
+        if let at::urc::URCMessages::PeerToPeerMessage { rssi, snr, data } = urcs.next_urc().await {
+            return (rssi, snr, data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        future::{pending, poll_fn, Future},
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+    use crate::{
+        testing::{MockClock, MockModule, RxState},
+        ConfigurationParameter,
+    };
+    use alloc::{string::String, vec::Vec};
+
+    impl AsyncClient for MockModule {
+        async fn send<A, const LEN: usize>(
+            &mut self,
+            command: &A,
+        ) -> Result<A::Response, atat::Error>
+        where
+            A: atat::AtatCmd<LEN>,
+        {
+            nb::block!(atat::AtatClient::send(self, command))
+        }
+    }
+
+    /// The URCs of a [`MockModule`], pending until the module reports one.
+    struct MockUrcs(MockModule);
+
+    impl UrcSubscription for MockUrcs {
+        async fn next_urc(&mut self) -> at::urc::URCMessages {
+            poll_fn(|_| {
+                atat::AtatClient::check_urc::<at::urc::URCMessages>(&mut self.0)
+                    .map_or(Poll::Pending, Poll::Ready)
+            })
+            .await
+        }
+    }
+
+    /// A timer which completes at once if expired, and never otherwise.
+    #[derive(Default)]
+    struct MockTimer {
+        expired: bool,
+        durations: Vec<fugit::MicrosDurationU32>,
+    }
+
+    impl Timer for MockTimer {
+        async fn after(&mut self, duration: fugit::MicrosDurationU32) {
+            self.durations.push(duration);
+            if !self.expired {
+                pending::<()>().await;
+            }
+        }
+    }
+
+    fn radio(module: &MockModule) -> AsyncRui3Radio<MockModule, MockUrcs> {
+        AsyncRui3Radio::new(module.clone(), MockUrcs(module.clone()))
+    }
+
+    /// Polls a future once, without an executor.
+    fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+        pin!(future).poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn configure_sends_every_parameter_then_the_changed_ones() {
+        let module = MockModule::new();
+        let mut radio = radio(&module);
+        let mut timer = MockTimer::default();
+        let configuration = Configuration {
+            encryption_key: "0123456789ABCDEF".into(),
+            ..Configuration::default()
+        };
+
+        let Poll::Ready(changes) = poll_once(radio.configure(configuration.clone(), &mut timer))
+        else {
+            panic!("configure is pending");
+        };
+        assert_eq!(changes.unwrap().len(), 12);
+        assert_eq!(
+            module.commands(),
+            [
+                "+NWM=0",
+                "",
+                "+P2P=868000000:7:0:0:8:14",
+                "+ENCKEY=0123456789ABCDEF",
+                "+ENCRY=0",
+                "+SYNCWORD=3444",
+                "+IQINVER=0",
+                "+SYMBOLTIMEOUT=0",
+            ]
+            .map(String::from)
+        );
+
+        module.clear_commands();
+        let configuration = Configuration {
+            sync_word: 0x1424,
+            ..configuration
+        };
+        let Poll::Ready(changes) = poll_once(radio.configure(configuration, &mut timer)) else {
+            panic!("configure is pending");
+        };
+        assert_eq!(&changes.unwrap()[..], [ConfigurationParameter::SyncWord]);
+        assert_eq!(module.commands(), ["+SYNCWORD=1424"].map(String::from));
+        assert_eq!(radio.configuration().sync_word, 0x1424);
+    }
+
+    #[test]
+    fn configure_only_records_the_applied_parameters() {
+        let module = MockModule::new();
+        let mut radio = radio(&module);
+        let mut timer = MockTimer::default();
+        module.fail_next("+SYNCWORD", &Rui3Error::ParamError);
+        let configuration = Configuration {
+            frequency: 869_000_000,
+            sync_word: 0x1424,
+            ..Configuration::default()
+        };
+
+        let Poll::Ready(result) = poll_once(radio.configure(configuration.clone(), &mut timer))
+        else {
+            panic!("configure is pending");
+        };
+        assert_eq!(result, Err(Rui3Error::ParamError));
+        assert_eq!(radio.configuration().frequency, 869_000_000);
+        assert_eq!(radio.configuration().sync_word, 0x3444);
+
+        // Every parameter is sent again.
+        module.clear_commands();
+        let Poll::Ready(changes) = poll_once(radio.configure(configuration, &mut timer)) else {
+            panic!("configure is pending");
+        };
+        assert_eq!(changes.unwrap().len(), 11);
+        assert_eq!(radio.configuration().sync_word, 0x1424);
+    }
+
+    #[test]
+    fn configure_falls_back_to_one_command_per_parameter() {
+        let module = MockModule::new();
+        let mut radio = radio(&module);
+        let mut timer = MockTimer::default();
+        module.set_unsupported("+P2P");
+        let configuration = Configuration {
+            spreading_factor: 10,
+            ..Configuration::default()
+        };
+
+        let Poll::Ready(changes) = poll_once(radio.configure(configuration, &mut timer)) else {
+            panic!("configure is pending");
+        };
+        assert_eq!(changes.unwrap().len(), 11);
+        assert_eq!(module.parameter("+PSF").as_deref(), Some("10"));
+
+        // The combined command is not tried again.
+        module.clear_commands();
+        let configuration = Configuration {
+            tx_power: 20,
+            ..Configuration::default()
+        };
+        let Poll::Ready(changes) = poll_once(radio.configure(configuration, &mut timer)) else {
+            panic!("configure is pending");
+        };
+        assert_eq!(
+            &changes.unwrap()[..],
+            [
+                ConfigurationParameter::SpreadingFactor,
+                ConfigurationParameter::TxPower,
+            ]
+        );
+        assert_eq!(module.commands(), ["+PSF=7", "+PTP=20"].map(String::from));
+    }
+
+    #[test]
+    fn configure_waits_for_the_restart_after_a_working_mode_change() {
+        let module = MockModule::new();
+        let mut radio = radio(&module);
+        let mut timer = MockTimer {
+            expired: true,
+            ..MockTimer::default()
+        };
+        let configuration = Configuration {
+            working_mode: at::commands::p2p::WorkingMode::FskP2P,
+            bandwidth: at::commands::p2p::Bandwidth::Fsk(125_000),
+            ..Configuration::default()
+        };
+
+        let Poll::Ready(changes) = poll_once(radio.configure(configuration, &mut timer)) else {
+            panic!("configure is pending");
+        };
+        assert!(changes.is_ok());
+        // The module ignores the first polls while it restarts.
+        let commands = module.commands();
+        let restart = commands.iter().position(|c| c == "+NWM=2").unwrap();
+        assert_eq!(
+            commands[restart + 1..restart + 5],
+            ["", "", "", "+PFREQ=868000000"]
+        );
+        assert_eq!(timer.durations.len(), 2);
+        assert_eq!(module.parameter("+PBW").as_deref(), Some("125000"));
+    }
+
+    #[test]
+    fn send_waits_for_the_end_of_the_transmission() {
+        let module = MockModule::new();
+        let mut radio = radio(&module);
+        let mut timer = MockTimer::default();
+
+        assert_eq!(
+            poll_once(radio.send(&[1, 2], &mut timer)),
+            Poll::Ready(Ok(()))
+        );
+        assert_eq!(module.transmitted(), [[1, 2]]);
+        assert_eq!(module.rx_state(), RxState::Continuous);
+    }
+
+    #[test]
+    fn send_times_out() {
+        let module = MockModule::new();
+        let mut radio = radio(&module);
+        module.set_auto_tx_done(false);
+
+        let mut timer = MockTimer {
+            expired: true,
+            ..MockTimer::default()
+        };
+
+        assert_eq!(
+            poll_once(radio.send(&[1, 2], &mut timer)),
+            Poll::Ready(Err(Rui3Error::TxTimeout))
+        );
+        let timeout =
+            Configuration::default().time_on_air(2) + fugit::MicrosDurationU32::millis(500);
+        assert_eq!(timer.durations, [timeout]);
+    }
+
+    #[test]
+    fn send_reports_a_busy_channel() {
+        let module = MockModule::new();
+        let mut radio = radio(&module);
+        module.set_parameter("+CAD", "1");
+        module.set_busy_detections(1);
+
+        assert_eq!(
+            poll_once(radio.send(&[1], &mut MockTimer::default())),
+            Poll::Ready(Err(Rui3Error::ChannelBusy))
+        );
+        assert!(module.transmitted().is_empty());
+        assert_eq!(module.rx_state(), RxState::Continuous);
+    }
+
+    #[test]
+    fn receive_returns_the_first_packet_or_times_out() {
+        let module = MockModule::new();
+        let mut radio = radio(&module);
+        let mut clock = MockClock::<1000>::new();
+        let timeout = fugit::MillisDurationU32::secs(1);
+        module.inject_packet(-60, 5, &[7, 8]);
+
+        let Poll::Ready(Ok(Some(packet))) =
+            poll_once(radio.receive(timeout, &mut clock, &mut MockTimer::default()))
+        else {
+            panic!("no packet received");
+        };
+        assert_eq!(&packet.payload[..], &[7, 8]);
+        assert_eq!((packet.rssi, packet.snr), (-60, 5));
+
+        let mut timer = MockTimer {
+            expired: true,
+            ..MockTimer::default()
+        };
+        assert_eq!(
+            poll_once(radio.receive(timeout, &mut clock, &mut timer)),
+            Poll::Ready(Ok(None))
+        );
+        assert_eq!(timer.durations, [fugit::MicrosDurationU32::secs(1)]);
+        assert_eq!(module.rx_state(), RxState::Continuous);
+    }
+}
@@ -29,8 +29,10 @@ pub struct FirmwareVersion {}
 pub struct HardwareModel {}
 
 #[derive(Clone, AtatCmd)]
-#[at_cmd("+ALIAS", NoResponse)]
-pub struct SetAlias {}
+#[at_cmd("+ALIAS", NoResponse, quote_escape_strings = false)]
+pub struct SetAlias {
+    pub alias: atat::heapless::String<16>,
+}
 
 #[derive(Clone, AtatCmd)]
 #[at_cmd("+ALIAS=?", AliasResponse)]
@@ -0,0 +1,360 @@
+#[allow(clippy::wildcard_imports)]
+use crate::at::responses::{lorawan::*, NoResponse};
+use atat::{
+    atat_derive::{AtatCmd, AtatEnum},
+    serde_at::serde::{de::Error as _, Deserialize},
+    AtatCmd, AtatLen,
+};
+use core::fmt::Write;
+
+#[derive(Clone, AtatEnum, Debug, PartialEq, Eq)]
+pub enum JoinMode {
+    Abp = 0,
+    Otaa = 1,
+}
+
+#[derive(Clone, AtatEnum, Debug, PartialEq, Eq)]
+pub enum JoinStatus {
+    NotJoined = 0,
+    Joined = 1,
+}
+
+#[derive(Clone, AtatEnum, Debug, PartialEq, Eq)]
+pub enum ConfirmMode {
+    Unconfirmed = 0,
+    Confirmed = 1,
+}
+
+#[derive(Clone, AtatEnum, Debug, PartialEq, Eq)]
+pub enum ConfirmStatus {
+    NotConfirmed = 0,
+    Confirmed = 1,
+}
+
+#[derive(Clone, AtatEnum, Debug, PartialEq, Eq)]
+pub enum AdaptiveDataRate {
+    Disabled = 0,
+    Enabled = 1,
+}
+
+#[derive(Clone, AtatEnum, Debug, PartialEq, Eq)]
+pub enum Band {
+    EU433 = 0,
+    CN470 = 1,
+    RU864 = 2,
+    IN865 = 3,
+    EU868 = 4,
+    US915 = 5,
+    AU915 = 6,
+    KR920 = 7,
+    AS923_1 = 8,
+    AS923_2 = 9,
+    AS923_3 = 10,
+    AS923_4 = 11,
+    LA915 = 12,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Class {
+    A,
+    B,
+    C,
+}
+
+impl Class {
+    const fn as_char(self) -> char {
+        match self {
+            Self::A => 'A',
+            Self::B => 'B',
+            Self::C => 'C',
+        }
+    }
+}
+
+impl AtatLen for Class {
+    const LEN: usize = 1;
+}
+
+impl<'a> Deserialize<'a> for Class {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: atat::serde_at::serde::Deserializer<'a>,
+    {
+        let s: &str = atat::serde_at::serde::Deserialize::deserialize(deserializer)?;
+        // The module may report the class with extra information, e.g. `B:S0`.
+        match s.get(..1) {
+            Some("A") => Ok(Self::A),
+            Some("B") => Ok(Self::B),
+            Some("C") => Ok(Self::C),
+            _ => Err(D::Error::custom("unknown LoRaWAN class")),
+        }
+    }
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+DEVEUI", NoResponse, quote_escape_strings = false)]
+pub struct SetDevEui {
+    pub dev_eui: atat::heapless::String<16>,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+DEVEUI=?", DevEuiResponse)]
+pub struct GetDevEui {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+APPEUI", NoResponse, quote_escape_strings = false)]
+pub struct SetAppEui {
+    pub app_eui: atat::heapless::String<16>,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+APPEUI=?", AppEuiResponse)]
+pub struct GetAppEui {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+APPKEY", NoResponse, quote_escape_strings = false)]
+pub struct SetAppKey {
+    pub app_key: atat::heapless::String<32>,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+APPKEY=?", AppKeyResponse)]
+pub struct GetAppKey {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+DEVADDR", NoResponse, quote_escape_strings = false)]
+pub struct SetDevAddr {
+    pub dev_addr: atat::heapless::String<8>,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+DEVADDR=?", DevAddrResponse)]
+pub struct GetDevAddr {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+APPSKEY", NoResponse, quote_escape_strings = false)]
+pub struct SetAppSKey {
+    pub app_s_key: atat::heapless::String<32>,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+APPSKEY=?", AppSKeyResponse)]
+pub struct GetAppSKey {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+NWKSKEY", NoResponse, quote_escape_strings = false)]
+pub struct SetNwkSKey {
+    pub nwk_s_key: atat::heapless::String<32>,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+NWKSKEY=?", NwkSKeyResponse)]
+pub struct GetNwkSKey {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+NJM", NoResponse)]
+pub struct SetJoinMode {
+    pub mode: JoinMode,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+NJM=?", JoinModeResponse)]
+pub struct GetJoinMode {}
+
+/// `AT+JOIN=<join>:<auto_join>:<interval>:<attempts>`.
+#[derive(Clone)]
+pub struct Join {
+    /// `true` to start joining, `false` to stop.
+    pub join: bool,
+    /// Whether the module joins automatically on power up.
+    pub auto_join: bool,
+    /// Seconds between join attempts, from 7 to 255.
+    pub interval: u8,
+    /// Number of join attempts, `0` to retry forever.
+    pub attempts: u8,
+}
+
+impl AtatCmd<32> for Join {
+    type Response = NoResponse;
+
+    fn as_bytes(&self) -> atat::heapless::Vec<u8, 32> {
+        let mut command: atat::heapless::String<32> = atat::heapless::String::new();
+        write!(
+            command,
+            "AT+JOIN={}:{}:{}:{}\r\n",
+            u8::from(self.join),
+            u8::from(self.auto_join),
+            self.interval,
+            self.attempts
+        )
+        .unwrap();
+        command.into_bytes()
+    }
+
+    fn parse(
+        &self,
+        resp: Result<&[u8], atat::InternalError>,
+    ) -> Result<Self::Response, atat::Error> {
+        resp.map(|_| NoResponse {}).map_err(atat::Error::from)
+    }
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+NJS=?", JoinStatusResponse)]
+pub struct GetJoinStatus {}
+
+/// `AT+SEND=<port>:<payload>`, with the payload hex encoded.
+#[derive(Clone)]
+pub struct SendData {
+    pub port: u8,
+    pub payload: atat::heapless::String<500>,
+}
+
+impl AtatCmd<1024> for SendData {
+    type Response = NoResponse;
+
+    fn as_bytes(&self) -> atat::heapless::Vec<u8, 1024> {
+        let mut bytes = atat::heapless::Vec::new();
+        let mut port: atat::heapless::String<4> = atat::heapless::String::new();
+        write!(port, "{}:", self.port).unwrap();
+        bytes.extend_from_slice(b"AT+SEND=").unwrap();
+        bytes.extend_from_slice(port.as_bytes()).unwrap();
+        bytes.extend_from_slice(self.payload.as_bytes()).unwrap();
+        bytes.extend_from_slice(b"\r\n").unwrap();
+        bytes
+    }
+
+    fn parse(
+        &self,
+        resp: Result<&[u8], atat::InternalError>,
+    ) -> Result<Self::Response, atat::Error> {
+        resp.map(|_| NoResponse {}).map_err(atat::Error::from)
+    }
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+CFM", NoResponse)]
+pub struct SetConfirmMode {
+    pub mode: ConfirmMode,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+CFM=?", ConfirmModeResponse)]
+pub struct GetConfirmMode {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+CFS=?", ConfirmStatusResponse)]
+pub struct GetConfirmStatus {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+ADR", NoResponse)]
+pub struct SetAdaptiveDataRate {
+    pub adr: AdaptiveDataRate,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+ADR=?", AdaptiveDataRateResponse)]
+pub struct GetAdaptiveDataRate {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+DR", NoResponse)]
+pub struct SetDataRate {
+    pub data_rate: u8,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+DR=?", DataRateResponse)]
+pub struct GetDataRate {}
+
+/// `AT+CLASS=<class>`.
+#[derive(Clone)]
+pub struct SetClass {
+    pub class: Class,
+}
+
+impl AtatCmd<16> for SetClass {
+    type Response = NoResponse;
+
+    fn as_bytes(&self) -> atat::heapless::Vec<u8, 16> {
+        let mut command: atat::heapless::String<16> = atat::heapless::String::new();
+        write!(command, "AT+CLASS={}\r\n", self.class.as_char()).unwrap();
+        command.into_bytes()
+    }
+
+    fn parse(
+        &self,
+        resp: Result<&[u8], atat::InternalError>,
+    ) -> Result<Self::Response, atat::Error> {
+        resp.map(|_| NoResponse {}).map_err(atat::Error::from)
+    }
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+CLASS=?", ClassResponse)]
+pub struct GetClass {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+BAND", NoResponse)]
+pub struct SetBand {
+    pub band: Band,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+BAND=?", BandResponse)]
+pub struct GetBand {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+MASK", NoResponse, quote_escape_strings = false)]
+pub struct SetChannelMask {
+    pub mask: atat::heapless::String<4>,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+MASK=?", ChannelMaskResponse)]
+pub struct GetChannelMask {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+RETY", NoResponse)]
+pub struct SetRetransmissions {
+    pub retransmissions: u8,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+RETY=?", RetransmissionsResponse)]
+pub struct GetRetransmissions {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join() {
+        let command = Join {
+            join: true,
+            auto_join: false,
+            interval: 8,
+            attempts: 3,
+        };
+        assert_eq!(&command.as_bytes()[..], b"AT+JOIN=1:0:8:3\r\n");
+    }
+
+    #[test]
+    fn send_data() {
+        let command = SendData {
+            port: 2,
+            payload: "0102".into(),
+        };
+        assert_eq!(&command.as_bytes()[..], b"AT+SEND=2:0102\r\n");
+    }
+
+    #[test]
+    fn class() {
+        let command = SetClass { class: Class::C };
+        assert_eq!(&command.as_bytes()[..], b"AT+CLASS=C\r\n");
+
+        assert_eq!(GetClass {}.parse(Ok(b"A")).unwrap().class, Class::A);
+        // Class B is reported with the beacon state.
+        assert_eq!(GetClass {}.parse(Ok(b"B:S0")).unwrap().class, Class::B);
+        assert!(GetClass {}.parse(Ok(b"D")).is_err());
+    }
+}
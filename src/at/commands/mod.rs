@@ -1,2 +1,47 @@
+use crate::error::Rui3Error;
+use atat::{AtatCmd, AtatResp};
+
 pub mod general;
+pub mod lorawan;
 pub mod p2p;
+
+/// Wraps a command so that RUI3 error lines are returned as [`Rui3Error`]s.
+///
+/// Without the wrapper every `AT_*_ERROR` line collapses into [`atat::Error::Custom`].
+#[derive(Clone)]
+pub struct Checked<'a, C>(pub &'a C);
+
+/// Response of a [`Checked`] command.
+pub struct CheckedResponse<R>(pub Result<R, Rui3Error>);
+
+impl<R: AtatResp> AtatResp for CheckedResponse<R> {}
+
+impl<C, const LEN: usize> AtatCmd<LEN> for Checked<'_, C>
+where
+    C: AtatCmd<LEN>,
+{
+    type Response = CheckedResponse<C::Response>;
+
+    const MAX_TIMEOUT_MS: u32 = C::MAX_TIMEOUT_MS;
+    const CAN_ABORT: bool = C::CAN_ABORT;
+    const EXPECTS_RESPONSE_CODE: bool = C::EXPECTS_RESPONSE_CODE;
+
+    fn as_bytes(&self) -> atat::heapless::Vec<u8, LEN> {
+        self.0.as_bytes()
+    }
+
+    fn parse(
+        &self,
+        resp: Result<&[u8], atat::InternalError>,
+    ) -> Result<Self::Response, atat::Error> {
+        match resp {
+            Ok(resp) => Ok(CheckedResponse(
+                self.0.parse(Ok(resp)).map_err(Rui3Error::from),
+            )),
+            // Transport errors still go through the client, so that `send_retry` can
+            // act on timeouts.
+            Err(atat::InternalError::Timeout) => Err(atat::Error::Timeout),
+            Err(error) => Ok(CheckedResponse(Err(Rui3Error::from_internal(error)))),
+        }
+    }
+}
@@ -2,18 +2,19 @@
 use crate::at::responses::{p2p::*, NoResponse};
 use atat::{
     atat_derive::{AtatCmd, AtatEnum},
-    serde_at::serde::{Deserialize, Serialize},
+    serde_at::serde::{de::Error as _, Deserialize, Serialize},
     AtatCmd, AtatLen,
 };
+use core::fmt::Write;
 
-#[derive(Clone, AtatEnum, Debug)]
+#[derive(Clone, AtatEnum, Debug, PartialEq, Eq)]
 pub enum WorkingMode {
     LoRaP2P = 0,
     LoRaWan = 1,
     FskP2P = 2,
 }
 
-#[derive(Clone, AtatEnum, Debug)]
+#[derive(Clone, AtatEnum, Debug, PartialEq, Eq)]
 pub enum CodeRate {
     PCR4_5 = 0,
     PCR4_6 = 1,
@@ -21,13 +22,27 @@ pub enum CodeRate {
     PCR4_8 = 3,
 }
 
-#[derive(Clone, AtatEnum, Debug)]
+#[derive(Clone, AtatEnum, Debug, PartialEq, Eq)]
 pub enum Encrypted {
     False = 0,
     True = 1,
 }
 
-#[derive(Clone, Debug)]
+/// IQ inversion, sent as 0 or 1 like RUI3 expects.
+#[derive(Clone, Copy, AtatEnum, Debug, PartialEq, Eq)]
+pub enum IqInversion {
+    Disabled = 0,
+    Enabled = 1,
+}
+
+/// Channel activity detection, sent as 0 or 1 like RUI3 expects.
+#[derive(Clone, Copy, AtatEnum, Debug, PartialEq, Eq)]
+pub enum ChannelActivityDetection {
+    Disabled = 0,
+    Enabled = 1,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Bandwidth {
     LoRa125KHz,
     LoRa250KHz,
@@ -42,6 +57,100 @@ pub enum Bandwidth {
     Fsk(u32),
 }
 
+impl From<bool> for IqInversion {
+    fn from(enabled: bool) -> Self {
+        if enabled {
+            Self::Enabled
+        } else {
+            Self::Disabled
+        }
+    }
+}
+
+impl From<IqInversion> for bool {
+    fn from(iq_inversion: IqInversion) -> Self {
+        iq_inversion == IqInversion::Enabled
+    }
+}
+
+impl From<bool> for ChannelActivityDetection {
+    fn from(enabled: bool) -> Self {
+        if enabled {
+            Self::Enabled
+        } else {
+            Self::Disabled
+        }
+    }
+}
+
+impl From<ChannelActivityDetection> for bool {
+    fn from(detection: ChannelActivityDetection) -> Self {
+        detection == ChannelActivityDetection::Enabled
+    }
+}
+
+impl WorkingMode {
+    /// Returns the working mode matching its value in AT commands.
+    #[cfg(feature = "serde")]
+    pub(crate) const fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::LoRaP2P),
+            1 => Some(Self::LoRaWan),
+            2 => Some(Self::FskP2P),
+            _ => None,
+        }
+    }
+}
+
+impl CodeRate {
+    /// Returns the code rate matching its value in AT commands.
+    pub(crate) const fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::PCR4_5),
+            1 => Some(Self::PCR4_6),
+            2 => Some(Self::PCR4_7),
+            3 => Some(Self::PCR4_8),
+            _ => None,
+        }
+    }
+}
+
+impl Bandwidth {
+    /// Returns the value of the bandwidth in AT commands.
+    pub(crate) const fn value(&self) -> u32 {
+        match self {
+            Self::LoRa125KHz => 0,
+            Self::LoRa250KHz => 1,
+            Self::LoRa500KHz => 2,
+            Self::LoRa7_8MHz => 3,
+            Self::LoRa10_4MHz => 4,
+            Self::LoRa15_63MHz => 5,
+            Self::LoRa20_83MHz => 6,
+            Self::LoRa31_25MHz => 7,
+            Self::LoRa41_67MHz => 8,
+            Self::LoRa62_5MHz => 9,
+            Self::Fsk(bw) => *bw,
+        }
+    }
+
+    /// Returns the bandwidth matching its value in AT commands.
+    pub(crate) const fn from_value(value: u32) -> Self {
+        match value {
+            0 => Self::LoRa125KHz,
+            1 => Self::LoRa250KHz,
+            2 => Self::LoRa500KHz,
+            3 => Self::LoRa7_8MHz,
+            4 => Self::LoRa10_4MHz,
+            5 => Self::LoRa15_63MHz,
+            6 => Self::LoRa20_83MHz,
+            7 => Self::LoRa31_25MHz,
+            8 => Self::LoRa41_67MHz,
+            9 => Self::LoRa62_5MHz,
+            bw => Self::Fsk(bw),
+        }
+    }
+}
+
 impl AtatLen for Bandwidth {
     const LEN: usize = 6;
 }
@@ -62,7 +171,7 @@ impl Serialize for Bandwidth {
             Self::LoRa31_25MHz => serializer.serialize_i8(7),
             Self::LoRa41_67MHz => serializer.serialize_i8(8),
             Self::LoRa62_5MHz => serializer.serialize_i8(9),
-            Self::Fsk(bw) => serializer.serialize_str(alloc::format!("{bw}").as_str()),
+            Self::Fsk(bw) => serializer.serialize_u32(*bw),
         }
     }
 }
@@ -84,7 +193,7 @@ impl<'a> Deserialize<'a> for Bandwidth {
             "7" => Ok(Self::LoRa31_25MHz),
             "8" => Ok(Self::LoRa41_67MHz),
             "9" => Ok(Self::LoRa62_5MHz),
-            _ => Ok(Self::Fsk(s.parse().unwrap())),
+            _ => s.parse().map(Self::Fsk).map_err(D::Error::custom),
         }
     }
 }
@@ -107,7 +216,7 @@ impl Serialize for ReceiveWindow {
         S: atat::serde_at::serde::Serializer,
     {
         match self {
-            Self::Milliseconds(ms) => serializer.serialize_str(alloc::format!("{ms}").as_str()),
+            Self::Milliseconds(ms) => serializer.serialize_u16(*ms),
             Self::OnePacket => serializer.serialize_i32(65535),
             Self::Continuous => serializer.serialize_i32(65534),
             Self::StopListening => serializer.serialize_i16(0),
@@ -197,7 +306,7 @@ impl AtatCmd<1024> for SendData {
         let mut bytes = atat::heapless::Vec::new();
         bytes.extend_from_slice(b"AT+PSEND=").unwrap();
         bytes
-            .extend_from_slice(self.payload.as_bytes().as_slice())
+            .extend_from_slice(self.payload.as_bytes())
             .unwrap();
         bytes
     }
@@ -227,7 +336,7 @@ pub struct SetEncryptionMode {
 pub struct GetEncryptionMode {}
 
 #[derive(Clone, AtatCmd)]
-#[at_cmd("+ENCKEY", NoResponse)]
+#[at_cmd("+ENCKEY", NoResponse, quote_escape_strings = false)]
 pub struct SetEncryptionKey {
     pub encryption_key: atat::heapless::String<16>,
 }
@@ -236,8 +345,8 @@ pub struct SetEncryptionKey {
 #[at_cmd("+ENCKEY=?", P2PEncryptionKey)]
 pub struct GetEncryptionKey {}
 
-#[derive(Clone, AtatCmd)]
-#[at_cmd("+P2P", NoResponse)]
+/// `AT+P2P=<frequency>:<spreading_factor>:<bandwidth>:<code_rate>:<preamble_length>:<tx_power>`.
+#[derive(Clone)]
 pub struct SetP2P {
     pub frequency: u32,
     pub spreading_factor: u8,
@@ -247,14 +356,76 @@ pub struct SetP2P {
     pub tx_power: u8,
 }
 
-#[derive(Clone, AtatCmd)]
-#[at_cmd("+P2P=?", P2Pparameters)]
+impl AtatCmd<64> for SetP2P {
+    type Response = NoResponse;
+
+    fn as_bytes(&self) -> atat::heapless::Vec<u8, 64> {
+        let mut command: atat::heapless::String<64> = atat::heapless::String::new();
+        write!(
+            command,
+            "AT+P2P={}:{}:{}:{}:{}:{}\r\n",
+            self.frequency,
+            self.spreading_factor,
+            self.bandwidth.value(),
+            self.code_rate.clone() as u8,
+            self.preamble_length,
+            self.tx_power
+        )
+        .unwrap();
+        command.into_bytes()
+    }
+
+    fn parse(
+        &self,
+        resp: Result<&[u8], atat::InternalError>,
+    ) -> Result<Self::Response, atat::Error> {
+        resp.map(|_| NoResponse {}).map_err(atat::Error::from)
+    }
+}
+
+/// `AT+P2P=?`, answered with the colon-separated parameters.
+#[derive(Clone)]
 pub struct GetP2P {}
 
+impl AtatCmd<16> for GetP2P {
+    type Response = P2Pparameters;
+
+    fn as_bytes(&self) -> atat::heapless::Vec<u8, 16> {
+        atat::heapless::Vec::from_slice(b"AT+P2P=?\r\n").unwrap()
+    }
+
+    fn parse(
+        &self,
+        resp: Result<&[u8], atat::InternalError>,
+    ) -> Result<Self::Response, atat::Error> {
+        let resp = core::str::from_utf8(resp?).map_err(|_| atat::Error::Parse)?;
+        // Skip the `AT+P2P=` echo, if any.
+        let values = resp.trim().rsplit('=').next().unwrap_or_default();
+        let mut fields = values.split(':').map(str::trim);
+        let mut next = || fields.next().ok_or(atat::Error::Parse);
+        let parameters = P2Pparameters {
+            frequency: next()?.parse().map_err(|_| atat::Error::Parse)?,
+            spreading_factor: next()?.parse().map_err(|_| atat::Error::Parse)?,
+            bandwidth: Bandwidth::from_value(next()?.parse().map_err(|_| atat::Error::Parse)?),
+            coderate: next()?
+                .parse()
+                .ok()
+                .and_then(CodeRate::from_value)
+                .ok_or(atat::Error::Parse)?,
+            preamblelength: next()?.parse().map_err(|_| atat::Error::Parse)?,
+            txpower: next()?.parse().map_err(|_| atat::Error::Parse)?,
+        };
+        if fields.next().is_some() {
+            return Err(atat::Error::Parse);
+        }
+        Ok(parameters)
+    }
+}
+
 #[derive(Clone, AtatCmd)]
 #[at_cmd("+IQINVER", NoResponse)]
 pub struct SetIqInversion {
-    pub iq_inversion: bool,
+    pub iq_inversion: IqInversion,
 }
 
 #[derive(Clone, AtatCmd)]
@@ -262,7 +433,7 @@ pub struct SetIqInversion {
 pub struct GetIqInversion {}
 
 #[derive(Clone, AtatCmd)]
-#[at_cmd("+SYNCWORD", NoResponse)]
+#[at_cmd("+SYNCWORD", NoResponse, quote_escape_strings = false)]
 pub struct SetSyncWord {
     pub sync_word: atat::heapless::String<4>,
 }
@@ -280,3 +451,107 @@ pub struct SetSymbolTimeout {
 #[derive(Clone, AtatCmd)]
 #[at_cmd("+SYMBOLTIMEOUT=?", P2PSymbolTimeout)]
 pub struct GetSymbolTimeout {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+PBR", NoResponse)]
+pub struct SetFskBitrate {
+    pub bitrate: u32,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+PBR=?", P2PFskBitrate)]
+pub struct GetFskBitrate {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+PFDEV", NoResponse)]
+pub struct SetFskDeviation {
+    pub deviation: u32,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+PFDEV=?", P2PFskDeviation)]
+pub struct GetFskDeviation {}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+CAD", NoResponse)]
+pub struct SetChannelActivityDetection {
+    pub detection: ChannelActivityDetection,
+}
+
+#[derive(Clone, AtatCmd)]
+#[at_cmd("+CAD=?", P2PChannelActivityDetection)]
+pub struct GetChannelActivityDetection {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combined_parameters() {
+        let command = SetP2P {
+            frequency: 868_000_000,
+            spreading_factor: 12,
+            bandwidth: Bandwidth::LoRa500KHz,
+            code_rate: CodeRate::PCR4_6,
+            preamble_length: 16,
+            tx_power: 22,
+        };
+        assert_eq!(
+            &command.as_bytes()[..],
+            b"AT+P2P=868000000:12:2:1:16:22\r\n"
+        );
+
+        for response in [
+            &b"868000000:12:2:1:16:22"[..],
+            b"AT+P2P=868000000:12:2:1:16:22\r\n",
+        ] {
+            let parameters = GetP2P {}.parse(Ok(response)).unwrap();
+            assert_eq!(parameters.frequency, 868_000_000);
+            assert_eq!(parameters.spreading_factor, 12);
+            assert_eq!(parameters.bandwidth, Bandwidth::LoRa500KHz);
+            assert_eq!(parameters.coderate, CodeRate::PCR4_6);
+            assert_eq!(parameters.preamblelength, 16);
+            assert_eq!(parameters.txpower, 22);
+        }
+
+        for response in [
+            &b"868000000:12:2:1:16"[..],
+            b"868000000:12:2:4:16:22",
+            b"1:2:3:0:5:6:7",
+        ] {
+            assert!(GetP2P {}.parse(Ok(response)).is_err());
+        }
+    }
+
+    #[test]
+    fn iq_inversion_is_sent_as_a_number() {
+        let command = SetIqInversion {
+            iq_inversion: true.into(),
+        };
+        assert_eq!(&command.as_bytes()[..], b"AT+IQINVER=1\r\n");
+        let command = SetIqInversion {
+            iq_inversion: IqInversion::Disabled,
+        };
+        assert_eq!(&command.as_bytes()[..], b"AT+IQINVER=0\r\n");
+
+        let response = GetIqInversion {}.parse(Ok(b"1")).unwrap();
+        assert_eq!(response.iq_inversion, IqInversion::Enabled);
+        assert!(bool::from(response.iq_inversion));
+    }
+
+    #[test]
+    fn channel_activity_detection_is_sent_as_a_number() {
+        let command = SetChannelActivityDetection {
+            detection: true.into(),
+        };
+        assert_eq!(&command.as_bytes()[..], b"AT+CAD=1\r\n");
+        let command = SetChannelActivityDetection {
+            detection: ChannelActivityDetection::Disabled,
+        };
+        assert_eq!(&command.as_bytes()[..], b"AT+CAD=0\r\n");
+
+        let response = GetChannelActivityDetection {}.parse(Ok(b"0")).unwrap();
+        assert_eq!(response.detection, ChannelActivityDetection::Disabled);
+        assert!(!bool::from(response.detection));
+    }
+}
@@ -0,0 +1,100 @@
+use crate::at::commands::lorawan::{
+    AdaptiveDataRate, Band, Class, ConfirmMode, ConfirmStatus, JoinMode, JoinStatus,
+};
+use atat::atat_derive::AtatResp;
+
+#[derive(Clone, AtatResp)]
+pub struct DevEuiResponse {
+    #[at_arg(position = 0)]
+    pub dev_eui: atat::heapless::String<16>,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct AppEuiResponse {
+    #[at_arg(position = 0)]
+    pub app_eui: atat::heapless::String<16>,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct AppKeyResponse {
+    #[at_arg(position = 0)]
+    pub app_key: atat::heapless::String<32>,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct DevAddrResponse {
+    #[at_arg(position = 0)]
+    pub dev_addr: atat::heapless::String<8>,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct AppSKeyResponse {
+    #[at_arg(position = 0)]
+    pub app_s_key: atat::heapless::String<32>,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct NwkSKeyResponse {
+    #[at_arg(position = 0)]
+    pub nwk_s_key: atat::heapless::String<32>,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct JoinModeResponse {
+    #[at_arg(position = 0)]
+    pub mode: JoinMode,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct JoinStatusResponse {
+    #[at_arg(position = 0)]
+    pub status: JoinStatus,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct ConfirmModeResponse {
+    #[at_arg(position = 0)]
+    pub mode: ConfirmMode,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct ConfirmStatusResponse {
+    #[at_arg(position = 0)]
+    pub status: ConfirmStatus,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct AdaptiveDataRateResponse {
+    #[at_arg(position = 0)]
+    pub adr: AdaptiveDataRate,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct DataRateResponse {
+    #[at_arg(position = 0)]
+    pub data_rate: u8,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct ClassResponse {
+    #[at_arg(position = 0)]
+    pub class: Class,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct BandResponse {
+    #[at_arg(position = 0)]
+    pub band: Band,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct ChannelMaskResponse {
+    #[at_arg(position = 0)]
+    pub mask: atat::heapless::String<4>,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct RetransmissionsResponse {
+    #[at_arg(position = 0)]
+    pub retransmissions: u8,
+}
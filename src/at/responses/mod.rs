@@ -1,6 +1,7 @@
 use atat::atat_derive::AtatResp;
 
 pub mod general;
+pub mod lorawan;
 pub mod p2p;
 
 #[derive(Clone, AtatResp)]
@@ -1,4 +1,4 @@
-use crate::at::commands::p2p::{Bandwidth, CodeRate, WorkingMode, Encrypted};
+use crate::at::commands::p2p::{Bandwidth, CodeRate, WorkingMode, Encrypted, IqInversion, ChannelActivityDetection};
 use atat::atat_derive::AtatResp;
 
 #[derive(Clone, AtatResp)]
@@ -59,17 +59,22 @@ pub struct P2PEncryptionKey {
 pub struct P2Pparameters {
     #[at_arg(position = 0)]
     pub frequency: u32,
+    #[at_arg(position = 1)]
     pub spreading_factor: u8,
+    #[at_arg(position = 2)]
     pub bandwidth: Bandwidth,
+    #[at_arg(position = 3)]
     pub coderate: CodeRate,
+    #[at_arg(position = 4)]
     pub preamblelength: u16,
+    #[at_arg(position = 5)]
     pub txpower: u8,
 }
 
 #[derive(Clone, AtatResp)]
 pub struct P2PIQInversion {
     #[at_arg(position = 0)]
-    pub iq_inversion: bool,
+    pub iq_inversion: IqInversion,
 }
 
 #[derive(Clone, AtatResp)]
@@ -89,3 +94,21 @@ pub struct P2PSymbolTimeout {
     #[at_arg(position = 0)]
     pub symbol_timeout: u8,
 }
+
+#[derive(Clone, AtatResp)]
+pub struct P2PFskBitrate {
+    #[at_arg(position = 0)]
+    pub bitrate: u32,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct P2PFskDeviation {
+    #[at_arg(position = 0)]
+    pub deviation: u32,
+}
+
+#[derive(Clone, AtatResp)]
+pub struct P2PChannelActivityDetection {
+    #[at_arg(position = 0)]
+    pub detection: ChannelActivityDetection,
+}
@@ -1,60 +1,567 @@
-use alloc::string::String;
-use alloc::vec::Vec;
+use crate::at::commands::lorawan::Class;
 use atat::{AtatUrc, Parser};
-use text_io::scan;
 
+/// Maximum length in bytes of a P2P payload.
+pub const MAX_PAYLOAD_LEN: usize = 255;
+
+/// A received payload, stored without heap allocation.
+pub type Payload = atat::heapless::Vec<u8, MAX_PAYLOAD_LEN>;
+
+/// Maximum length of the raw text kept for unknown events.
+pub const MAX_UNKNOWN_LEN: usize = 64;
+
+/// Why an `RXP2P` event could not be parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerToPeerParseError {
+    /// The line does not start with `RXP2P:`.
+    NotPeerToPeer,
+    /// The RSSI is missing or is not a decimal number.
+    InvalidRssi,
+    /// The SNR is missing or is not a decimal number.
+    InvalidSnr,
+    /// The payload is not an even number of hex digits.
+    InvalidPayload,
+    /// The payload is longer than [`MAX_PAYLOAD_LEN`] bytes.
+    PayloadTooLong,
+}
+
+/// Receive window a downlink arrived in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DownlinkWindow {
+    Rx1,
+    Rx2,
+    RxB,
+    RxC,
+}
+
+/// Events reported by the module as `+EVT:<event>` lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum URCMessages {
-    PeerToPeerData(Vec<u8>),
-    PeerToPeerInfo { rssi: i16, snr: i16 },
-    PeerToPeerMessage { rssi: i16, snr: i16, data: Vec<u8> },
+    /// `RXP2P:<rssi>:<snr>:<payload>`
+    PeerToPeerMessage { rssi: i16, snr: i16, data: Payload },
+    /// `TXP2P DONE`
+    PeerToPeerTxDone,
+    /// `RXP2P RECEIVE TIMEOUT`
+    PeerToPeerReceiveTimeout,
+    /// `RXP2P RECEIVE ERROR`
+    PeerToPeerReceiveError,
+    /// `JOINED`
+    Joined,
+    /// `JOIN_FAILED_<reason>`, e.g. `JOIN_FAILED_RX_TIMEOUT`.
+    JoinFailed,
+    /// `TX_DONE`
+    TxDone,
+    /// `SEND_CONFIRMED_OK`
+    SendConfirmedOk,
+    /// `SEND_CONFIRMED_FAILED`
+    SendConfirmedFailed,
+    /// `RX_<window>:<rssi>:<snr>:<UNICAST|MULTICAST>:<port>:<payload>`
+    Downlink {
+        window: DownlinkWindow,
+        rssi: i16,
+        snr: i16,
+        multicast: bool,
+        port: u8,
+        data: Payload,
+    },
+    /// `LINKCHECK:<status>:<demod_margin>:<gateways>:<rssi>:<snr>`
+    LinkCheck {
+        status: u8,
+        demod_margin: u8,
+        gateways: u8,
+        rssi: i16,
+        snr: i16,
+    },
+    /// `BEACON_LOCKED`
+    BeaconLocked,
+    /// `BEACON_LOST`
+    BeaconLost,
+    /// `BEACON_FAILED`
+    BeaconFailed,
+    /// `SWITCH_TO_CLASS_<class>`
+    SwitchedToClass(Class),
+    /// `CAD DETECTED`: channel activity detected, the channel is busy.
+    CadDetected,
+    /// `CAD DONE`: no channel activity detected, the channel is free.
+    CadDone,
+    /// Any other event, truncated to [`MAX_UNKNOWN_LEN`] characters.
+    Unknown(atat::heapless::String<MAX_UNKNOWN_LEN>),
 }
 
 impl AtatUrc for URCMessages {
     type Response = Self;
 
     fn parse(resp: &[u8]) -> Option<Self::Response> {
-        let status = String::from_utf8(resp.to_vec()).unwrap();
-
-        // Peer to peer info.
-        if status.starts_with("RXP2P") {
-            let rssi: i16;
-            let snr: i16;
-            let data_byte: i16;
-            let mut data = Vec::new();
-            let status_clone = status.clone();
-
-            scan!(status_clone.bytes() => "RXP2P:{}:{}", rssi, snr);
-
-            // TODO: Remove chars untill "snr:".
-            
-            // Peer to peer data.
-            if status.chars().all(|c| "0123456789ABCDEF".contains(c)) && status.len() % 2 == 0 {
-                // All the characters are hexadecimal.
-    
-                // Split characters two by two and convert them to bytes.
-                for i in (0..status.len()).step_by(2) {
-                    let byte = u8::from_str_radix(&status[i..i + 2], 16).unwrap();
-                    data.push(byte);
+        let resp = resp.trim_ascii();
+        if resp.starts_with(b"RXP2P:") {
+            return match parse_peer_to_peer_message(resp) {
+                Ok((rssi, snr, data)) => Some(Self::PeerToPeerMessage { rssi, snr, data }),
+                Err(error) => {
+                    log::warn!("Dropping malformed RXP2P event: {error:?}");
+                    None
                 }
-            }
-            return Some(Self::PeerToPeerMessage{rssi, snr, data});
+            };
         }
 
+        let status = core::str::from_utf8(resp).ok()?.trim();
 
-        None
+        let event = match status {
+            "TXP2P DONE" => Self::PeerToPeerTxDone,
+            "RXP2P RECEIVE TIMEOUT" => Self::PeerToPeerReceiveTimeout,
+            "RXP2P RECEIVE ERROR" => Self::PeerToPeerReceiveError,
+            "JOINED" => Self::Joined,
+            "TX_DONE" => Self::TxDone,
+            "SEND_CONFIRMED_OK" => Self::SendConfirmedOk,
+            "SEND_CONFIRMED_FAILED" => Self::SendConfirmedFailed,
+            "BEACON_LOCKED" => Self::BeaconLocked,
+            "BEACON_LOST" => Self::BeaconLost,
+            "BEACON_FAILED" => Self::BeaconFailed,
+            "CAD DETECTED" => Self::CadDetected,
+            "CAD DONE" => Self::CadDone,
+            "SWITCH_TO_CLASS_A" => Self::SwitchedToClass(Class::A),
+            "SWITCH_TO_CLASS_B" => Self::SwitchedToClass(Class::B),
+            "SWITCH_TO_CLASS_C" => Self::SwitchedToClass(Class::C),
+            status if status.starts_with("JOIN_FAILED") => Self::JoinFailed,
+            status => parse_fields(status).unwrap_or_else(|| Self::Unknown(truncate(status))),
+        };
+
+        Some(event)
     }
 }
 
 impl Parser for URCMessages {
     fn parse(buf: &[u8]) -> core::result::Result<(&[u8], usize), atat::digest::ParseError> {
-        if buf.len() < 5 {
-            return Err(atat::digest::ParseError::NoMatch);
+        const PREFIX: &[u8] = b"+EVT:";
+
+        let start = buf
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .ok_or(atat::digest::ParseError::NoMatch)?;
+        let line = &buf[start..];
+
+        if !line.starts_with(PREFIX) {
+            return if PREFIX.starts_with(line) {
+                Err(atat::digest::ParseError::Incomplete)
+            } else {
+                Err(atat::digest::ParseError::NoMatch)
+            };
+        }
+
+        // Consume the whole line, handing out the event without prefix and line end.
+        let end = line
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or(atat::digest::ParseError::Incomplete)?;
+        Ok((&line[PREFIX.len()..end], start + end + 2))
+    }
+}
+
+/// Parses the events carrying colon-separated fields.
+fn parse_fields(status: &str) -> Option<URCMessages> {
+    let (name, fields) = status.split_once(':')?;
+    let mut fields = fields.split(':');
+
+    match name {
+        "RX_1" | "RX_2" | "RX_B" | "RX_C" => {
+            let window = match name {
+                "RX_1" => DownlinkWindow::Rx1,
+                "RX_2" => DownlinkWindow::Rx2,
+                "RX_B" => DownlinkWindow::RxB,
+                _ => DownlinkWindow::RxC,
+            };
+            let rssi = fields.next()?.trim().parse().ok()?;
+            let snr = fields.next()?.trim().parse().ok()?;
+            let multicast = match fields.next()?.trim() {
+                "UNICAST" => false,
+                "MULTICAST" => true,
+                _ => return None,
+            };
+            let port = fields.next()?.trim().parse().ok()?;
+            let data = decode_hex(fields.next().unwrap_or_default().trim().as_bytes()).ok()?;
+            Some(URCMessages::Downlink {
+                window,
+                rssi,
+                snr,
+                multicast,
+                port,
+                data,
+            })
         }
+        "LINKCHECK" => Some(URCMessages::LinkCheck {
+            status: fields.next()?.trim().parse().ok()?,
+            demod_margin: fields.next()?.trim().parse().ok()?,
+            gateways: fields.next()?.trim().parse().ok()?,
+            rssi: fields.next()?.trim().parse().ok()?,
+            snr: fields.next()?.trim().parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// Parses an `RXP2P:<rssi>:<snr>:<payload>` event into its RSSI, SNR and payload.
+///
+/// The parser works on raw bytes and never allocates nor panics, whatever the input:
+/// garbled serial data is reported as an error.
+///
+/// # Example
+///
+/// ```
+/// use rui3_at::at::urc::parse_peer_to_peer_message;
+///
+/// let (rssi, snr, data) = parse_peer_to_peer_message(b"RXP2P:-38:11:AABB").unwrap();
+/// assert_eq!((rssi, snr, &data[..]), (-38, 11, &[0xAA, 0xBB][..]));
+/// ```
+///
+/// # Errors
+///
+/// Returns a [`PeerToPeerParseError`] describing the first malformed field.
+pub fn parse_peer_to_peer_message(
+    line: &[u8],
+) -> Result<(i16, i16, Payload), PeerToPeerParseError> {
+    let fields = line
+        .trim_ascii()
+        .strip_prefix(b"RXP2P:")
+        .ok_or(PeerToPeerParseError::NotPeerToPeer)?;
+    let mut fields = fields.splitn(3, |&b| b == b':');
 
-        if &buf[..=4] == b"+EVT:" {
-            Ok((&buf[5..], buf.len() - 5))
+    let rssi = fields
+        .next()
+        .and_then(parse_i16)
+        .ok_or(PeerToPeerParseError::InvalidRssi)?;
+    let snr = fields
+        .next()
+        .and_then(parse_i16)
+        .ok_or(PeerToPeerParseError::InvalidSnr)?;
+    // The module sends an empty payload as `RXP2P:<rssi>:<snr>:`, accept it without
+    // the trailing colon too.
+    let data = decode_hex(fields.next().unwrap_or_default().trim_ascii())?;
+
+    Ok((rssi, snr, data))
+}
+
+/// Parses a decimal `i16`, with an optional sign and surrounding spaces.
+fn parse_i16(field: &[u8]) -> Option<i16> {
+    let (negative, digits) = match field.trim_ascii() {
+        [b'-', digits @ ..] => (true, digits),
+        [b'+', digits @ ..] | digits => (false, digits),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+
+    // Accumulate towards the sign so that `i16::MIN` does not overflow.
+    digits.iter().try_fold(0_i16, |value, &digit| {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        let digit = i16::from(digit - b'0');
+        let value = value.checked_mul(10)?;
+        if negative {
+            value.checked_sub(digit)
         } else {
+            value.checked_add(digit)
+        }
+    })
+}
+
+/// Decodes hex digits, in either case, into a payload.
+fn decode_hex(hex: &[u8]) -> Result<Payload, PeerToPeerParseError> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(PeerToPeerParseError::InvalidPayload);
+    }
+    if hex.len() / 2 > MAX_PAYLOAD_LEN {
+        return Err(PeerToPeerParseError::PayloadTooLong);
+    }
+
+    let mut data = Payload::new();
+    for pair in hex.chunks_exact(2) {
+        let byte = (hex_digit(pair[0])? << 4) | hex_digit(pair[1])?;
+        data.push(byte)
+            .map_err(|_| PeerToPeerParseError::PayloadTooLong)?;
+    }
+    Ok(data)
+}
+
+/// Returns the value of a single hex digit.
+const fn hex_digit(digit: u8) -> Result<u8, PeerToPeerParseError> {
+    match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        b'A'..=b'F' => Ok(digit - b'A' + 10),
+        _ => Err(PeerToPeerParseError::InvalidPayload),
+    }
+}
+
+/// Keeps as much of an unknown event as fits in [`URCMessages::Unknown`].
+fn truncate(status: &str) -> atat::heapless::String<MAX_UNKNOWN_LEN> {
+    let mut raw = atat::heapless::String::new();
+    for c in status.chars() {
+        if raw.push(c).is_err() {
+            break;
+        }
+    }
+    raw
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{format, string::String, vec::Vec};
+
+    fn parse(line: &str) -> Option<URCMessages> {
+        <URCMessages as AtatUrc>::parse(line.as_bytes())
+    }
+
+    fn payload(data: &[u8]) -> Payload {
+        Payload::from_slice(data).unwrap()
+    }
+
+    #[test]
+    fn frames_event_lines() {
+        let buf = b"+EVT:TXP2P DONE\r\n+EVT:JOINED\r\n";
+        assert_eq!(
+            <URCMessages as Parser>::parse(buf),
+            Ok((&b"TXP2P DONE"[..], 17))
+        );
+        assert_eq!(
+            <URCMessages as Parser>::parse(b"\r\n+EVT:JOINED\r\n"),
+            Ok((&b"JOINED"[..], 15))
+        );
+        assert_eq!(
+            <URCMessages as Parser>::parse(b"+EVT:JOI"),
+            Err(atat::digest::ParseError::Incomplete)
+        );
+        assert_eq!(
+            <URCMessages as Parser>::parse(b"+EV"),
+            Err(atat::digest::ParseError::Incomplete)
+        );
+        assert_eq!(
+            <URCMessages as Parser>::parse(b"OK\r\n"),
             Err(atat::digest::ParseError::NoMatch)
+        );
+    }
+
+    #[test]
+    fn peer_to_peer_message() {
+        assert_eq!(
+            parse("RXP2P:-38:11:AABBCC"),
+            Some(URCMessages::PeerToPeerMessage {
+                rssi: -38,
+                snr: 11,
+                data: payload(&[0xAA, 0xBB, 0xCC]),
+            })
+        );
+    }
+
+    #[test]
+    fn peer_to_peer_status() {
+        assert_eq!(parse("TXP2P DONE"), Some(URCMessages::PeerToPeerTxDone));
+        assert_eq!(
+            parse("RXP2P RECEIVE TIMEOUT"),
+            Some(URCMessages::PeerToPeerReceiveTimeout)
+        );
+        assert_eq!(
+            parse("RXP2P RECEIVE ERROR"),
+            Some(URCMessages::PeerToPeerReceiveError)
+        );
+    }
+
+    #[test]
+    fn join_events() {
+        assert_eq!(parse("JOINED"), Some(URCMessages::Joined));
+        assert_eq!(
+            parse("JOIN_FAILED_RX_TIMEOUT"),
+            Some(URCMessages::JoinFailed)
+        );
+    }
+
+    #[test]
+    fn uplink_events() {
+        assert_eq!(parse("TX_DONE"), Some(URCMessages::TxDone));
+        assert_eq!(
+            parse("SEND_CONFIRMED_OK"),
+            Some(URCMessages::SendConfirmedOk)
+        );
+        assert_eq!(
+            parse("SEND_CONFIRMED_FAILED"),
+            Some(URCMessages::SendConfirmedFailed)
+        );
+    }
+
+    #[test]
+    fn downlinks() {
+        assert_eq!(
+            parse("RX_1:-70:8:UNICAST:2:1234"),
+            Some(URCMessages::Downlink {
+                window: DownlinkWindow::Rx1,
+                rssi: -70,
+                snr: 8,
+                multicast: false,
+                port: 2,
+                data: payload(&[0x12, 0x34]),
+            })
+        );
+        assert_eq!(
+            parse("RX_2:-112:-6:UNICAST:1:"),
+            Some(URCMessages::Downlink {
+                window: DownlinkWindow::Rx2,
+                rssi: -112,
+                snr: -6,
+                multicast: false,
+                port: 1,
+                data: Payload::new(),
+            })
+        );
+        assert_eq!(
+            parse("RX_B:-90:3:MULTICAST:10:FF"),
+            Some(URCMessages::Downlink {
+                window: DownlinkWindow::RxB,
+                rssi: -90,
+                snr: 3,
+                multicast: true,
+                port: 10,
+                data: payload(&[0xFF]),
+            })
+        );
+        assert!(matches!(
+            parse("RX_C:-80:5:MULTICAST:3:00"),
+            Some(URCMessages::Downlink {
+                window: DownlinkWindow::RxC,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn link_check() {
+        assert_eq!(
+            parse("LINKCHECK:0:20:2:-57:9"),
+            Some(URCMessages::LinkCheck {
+                status: 0,
+                demod_margin: 20,
+                gateways: 2,
+                rssi: -57,
+                snr: 9,
+            })
+        );
+    }
+
+    #[test]
+    fn class_b_events() {
+        assert_eq!(parse("BEACON_LOCKED"), Some(URCMessages::BeaconLocked));
+        assert_eq!(parse("BEACON_LOST"), Some(URCMessages::BeaconLost));
+        assert_eq!(parse("BEACON_FAILED"), Some(URCMessages::BeaconFailed));
+        assert_eq!(
+            parse("SWITCH_TO_CLASS_B"),
+            Some(URCMessages::SwitchedToClass(Class::B))
+        );
+        assert_eq!(
+            parse("SWITCH_TO_CLASS_A"),
+            Some(URCMessages::SwitchedToClass(Class::A))
+        );
+    }
+
+    #[test]
+    fn channel_activity_detection() {
+        assert_eq!(parse("CAD DETECTED"), Some(URCMessages::CadDetected));
+        assert_eq!(parse("CAD DONE"), Some(URCMessages::CadDone));
+    }
+
+    #[test]
+    fn unknown_events() {
+        assert_eq!(
+            parse("TIMEREQ"),
+            Some(URCMessages::Unknown("TIMEREQ".into()))
+        );
+        // Malformed known events are kept as unknown.
+        assert_eq!(
+            parse("LINKCHECK:0:x"),
+            Some(URCMessages::Unknown("LINKCHECK:0:x".into()))
+        );
+        let long = "X".repeat(100);
+        assert!(matches!(
+            parse(&long),
+            Some(URCMessages::Unknown(raw)) if raw.len() == MAX_UNKNOWN_LEN
+        ));
+    }
+
+    #[test]
+    fn malformed_peer_to_peer_messages() {
+        for line in [
+            "RXP2P:-38:11:ABC",
+            "RXP2P:-38:11:GG",
+            "RXP2P:-38:11:AA:BB",
+            "RXP2P:abc:11:AA",
+            "RXP2P:-38::AA",
+            "RXP2P:-99999:11:AA",
+            "RXP2P:-38",
+            "RXP2P:",
+        ] {
+            assert_eq!(parse(line), None, "{line}");
+        }
+        assert_eq!(
+            parse_peer_to_peer_message(b"RXP2P:-38:x:AA"),
+            Err(PeerToPeerParseError::InvalidSnr)
+        );
+        assert_eq!(
+            parse_peer_to_peer_message(b"TXP2P DONE"),
+            Err(PeerToPeerParseError::NotPeerToPeer)
+        );
+        let too_long = format!("RXP2P:-38:11:{}", "00".repeat(MAX_PAYLOAD_LEN + 1));
+        assert_eq!(
+            parse_peer_to_peer_message(too_long.as_bytes()),
+            Err(PeerToPeerParseError::PayloadTooLong)
+        );
+    }
+
+    #[test]
+    fn peer_to_peer_message_edge_cases() {
+        assert_eq!(
+            parse_peer_to_peer_message(b"RXP2P:-32768:32767:"),
+            Ok((i16::MIN, i16::MAX, Payload::new()))
+        );
+        assert_eq!(
+            parse_peer_to_peer_message(b" RXP2P:-38:11:aBcD\r\n"),
+            Ok((-38, 11, payload(&[0xAB, 0xCD])))
+        );
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn peer_to_peer_message_round_trip(
+            rssi: i16,
+            snr: i16,
+            data in proptest::collection::vec(proptest::num::u8::ANY, 0..=MAX_PAYLOAD_LEN),
+            lowercase: bool,
+        ) {
+            let hex: String = data
+                .iter()
+                .map(|b| if lowercase { format!("{b:02x}") } else { format!("{b:02X}") })
+                .collect();
+            let line = format!("RXP2P:{rssi}:{snr}:{hex}");
+            proptest::prop_assert_eq!(
+                parse(&line),
+                Some(URCMessages::PeerToPeerMessage { rssi, snr, data: payload(&data) })
+            );
+        }
+
+        #[test]
+        fn arbitrary_bytes_never_panic(line: Vec<u8>) {
+            let _ = <URCMessages as AtatUrc>::parse(&line);
+            let _ = <URCMessages as Parser>::parse(&line);
+            let _ = parse_peer_to_peer_message(&line);
+        }
+
+        #[test]
+        fn arbitrary_peer_to_peer_fields_never_panic(fields in "[-+0-9a-fA-FxX: ]{0,64}") {
+            let line = format!("RXP2P:{fields}");
+            if let Some(event) = parse(&line) {
+                proptest::prop_assert!(
+                    matches!(event, URCMessages::PeerToPeerMessage { .. }),
+                    "unexpected event {:?}",
+                    event
+                );
+            }
         }
     }
 }
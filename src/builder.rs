@@ -0,0 +1,483 @@
+//! Validated construction of a [`Configuration`].
+
+use core::fmt;
+
+use crate::{at, at::commands::lorawan::Band, at::commands::p2p::Encrypted, Configuration};
+
+/// Model of the RUI3 module, which bounds the frequency and TX power.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+    /// `RAK3172`, based on the STM32WLE5, from 150 MHz to 960 MHz.
+    Rak3172,
+    /// `RAK4630`, based on the nRF52840 and `SX1262`, matched for 863 MHz to 928 MHz.
+    Rak4630,
+    /// `RAK11720`, based on the Apollo3 and `SX1262`, matched for 863 MHz to 928 MHz.
+    Rak11720,
+}
+
+impl Model {
+    /// Returns the lowest and highest frequencies supported, in Hz.
+    const fn frequency_range(self) -> (u32, u32) {
+        match self {
+            Self::Rak3172 => (150_000_000, 960_000_000),
+            Self::Rak4630 | Self::Rak11720 => (863_000_000, 928_000_000),
+        }
+    }
+}
+
+/// Lowest TX power accepted by the modules, in dBm.
+const MIN_TX_POWER: u8 = 5;
+/// Highest TX power of the `SX126x` power amplifier, in dBm.
+const MAX_TX_POWER: u8 = 22;
+
+/// Returns the band plan of a region: lowest and highest frequencies in Hz, default
+/// frequency in Hz and highest TX power in dBm.
+const fn band_plan(region: &Band) -> (u32, u32, u32, u8) {
+    match region {
+        Band::EU433 => (433_050_000, 434_790_000, 433_375_000, 12),
+        Band::CN470 => (470_000_000, 510_000_000, 470_300_000, 19),
+        Band::RU864 => (864_000_000, 870_000_000, 868_900_000, 16),
+        Band::IN865 => (865_000_000, 867_000_000, 865_062_500, 22),
+        Band::EU868 => (863_000_000, 870_000_000, 868_000_000, 16),
+        Band::US915 | Band::LA915 => (902_000_000, 928_000_000, 915_000_000, 22),
+        Band::AU915 => (915_000_000, 928_000_000, 916_800_000, 22),
+        Band::KR920 => (920_900_000, 923_300_000, 922_100_000, 14),
+        Band::AS923_1 | Band::AS923_2 | Band::AS923_3 | Band::AS923_4 => {
+            (915_000_000, 928_000_000, 923_200_000, 16)
+        }
+    }
+}
+
+/// A reason why a [`ConfigurationBuilder`] cannot build a [`Configuration`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigurationError {
+    /// The spreading factor is not between 5 and 12.
+    SpreadingFactor(u8),
+    /// The frequency is outside the band plan of the region or the range of the
+    /// module.
+    Frequency {
+        /// The requested frequency, in Hz.
+        frequency: u32,
+        /// The lowest frequency allowed, in Hz.
+        min: u32,
+        /// The highest frequency allowed, in Hz.
+        max: u32,
+    },
+    /// The TX power exceeds the limit of the region or the module.
+    TxPower {
+        /// The requested TX power, in dBm.
+        tx_power: u8,
+        /// The lowest TX power allowed, in dBm.
+        min: u8,
+        /// The highest TX power allowed, in dBm.
+        max: u8,
+    },
+    /// The preamble is shorter than 2 symbols.
+    PreambleLength(u16),
+    /// The encryption key is not made of 16 hex digits.
+    EncryptionKey,
+    /// A `LoRa` bandwidth is used in FSK mode, or an FSK bandwidth in `LoRa` mode.
+    Bandwidth,
+    /// The FSK bandwidth is not between 4800 Hz and 467000 Hz.
+    FskBandwidth(u32),
+    /// The FSK bitrate is not between 600 b/s and 300000 b/s.
+    FskBitrate(u32),
+    /// The FSK frequency deviation is not between 600 Hz and 200000 Hz.
+    FskDeviation(u32),
+}
+
+impl fmt::Display for ConfigurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SpreadingFactor(sf) => {
+                write!(f, "spreading factor {sf} is not between 5 and 12")
+            }
+            Self::Frequency {
+                frequency,
+                min,
+                max,
+            } => write!(
+                f,
+                "frequency {frequency} Hz is not between {min} Hz and {max} Hz"
+            ),
+            Self::TxPower { tx_power, min, max } => write!(
+                f,
+                "TX power {tx_power} dBm is not between {min} dBm and {max} dBm"
+            ),
+            Self::PreambleLength(length) => {
+                write!(f, "preamble length {length} is shorter than 2 symbols")
+            }
+            Self::EncryptionKey => f.write_str("encryption key is not 16 hex digits"),
+            Self::Bandwidth => f.write_str("bandwidth does not match the working mode"),
+            Self::FskBandwidth(bandwidth) => write!(
+                f,
+                "FSK bandwidth {bandwidth} Hz is not between 4800 Hz and 467000 Hz"
+            ),
+            Self::FskBitrate(bitrate) => write!(
+                f,
+                "FSK bitrate {bitrate} b/s is not between 600 b/s and 300000 b/s"
+            ),
+            Self::FskDeviation(deviation) => write!(
+                f,
+                "FSK deviation {deviation} Hz is not between 600 Hz and 200000 Hz"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConfigurationError {}
+
+/// A builder checking a [`Configuration`] against the band plan of a region and
+/// the limits of the module before it is applied.
+///
+/// Starts from [`Configuration::default`], with the default frequency of the region.
+///
+/// # Example
+///
+/// ```compile_fail
+/// let configuration = Configuration::builder(Band::EU868, Model::Rak3172)
+///     .frequency(869_525_000)
+///     .spreading_factor(9)
+///     .tx_power(14)
+///     .build()?;
+/// radio_client.configure(configuration, &mut clock)?;
+/// ```
+#[derive(Clone, Debug)]
+#[must_use]
+pub struct ConfigurationBuilder {
+    region: Band,
+    model: Model,
+    configuration: Configuration,
+    /// Key set with [`ConfigurationBuilder::encryption_key`], checked when building.
+    encryption_key: Option<atat::heapless::String<32>>,
+}
+
+impl Configuration {
+    /// Returns a builder for a module of the given model used in the given region.
+    pub fn builder(region: Band, model: Model) -> ConfigurationBuilder {
+        ConfigurationBuilder::new(region, model)
+    }
+}
+
+impl ConfigurationBuilder {
+    /// Creates a builder for a module of the given model used in the given region.
+    pub fn new(region: Band, model: Model) -> Self {
+        let (_, _, frequency, _) = band_plan(&region);
+        Self {
+            region,
+            model,
+            configuration: Configuration {
+                frequency,
+                ..Configuration::default()
+            },
+            encryption_key: None,
+        }
+    }
+
+    /// Sets the working mode.
+    pub const fn working_mode(mut self, working_mode: at::commands::p2p::WorkingMode) -> Self {
+        self.configuration.working_mode = working_mode;
+        self
+    }
+
+    /// Sets the frequency, in Hz.
+    pub const fn frequency(mut self, frequency: u32) -> Self {
+        self.configuration.frequency = frequency;
+        self
+    }
+
+    /// Sets the spreading factor, from 5 to 12.
+    pub const fn spreading_factor(mut self, spreading_factor: u8) -> Self {
+        self.configuration.spreading_factor = spreading_factor;
+        self
+    }
+
+    /// Sets the bandwidth.
+    pub const fn bandwidth(mut self, bandwidth: at::commands::p2p::Bandwidth) -> Self {
+        self.configuration.bandwidth = bandwidth;
+        self
+    }
+
+    /// Sets the code rate.
+    pub const fn code_rate(mut self, code_rate: at::commands::p2p::CodeRate) -> Self {
+        self.configuration.code_rate = code_rate;
+        self
+    }
+
+    /// Sets the preamble length, in symbols.
+    pub const fn preamble_length(mut self, preamble_length: u16) -> Self {
+        self.configuration.preamble_length = preamble_length;
+        self
+    }
+
+    /// Sets the TX power, in dBm.
+    pub const fn tx_power(mut self, tx_power: u8) -> Self {
+        self.configuration.tx_power = tx_power;
+        self
+    }
+
+    /// Enables encryption with the given key of 16 hex digits.
+    pub fn encryption_key(mut self, encryption_key: &str) -> Self {
+        // Longer keys are reported when building.
+        let mut key = atat::heapless::String::new();
+        for c in encryption_key.chars().take(32) {
+            let _ = key.push(c);
+        }
+        self.encryption_key = Some(key);
+        self
+    }
+
+    /// Sets the FSK bitrate, in b/s.
+    pub const fn fsk_bitrate(mut self, fsk_bitrate: u32) -> Self {
+        self.configuration.fsk_bitrate = fsk_bitrate;
+        self
+    }
+
+    /// Sets the FSK frequency deviation, in Hz.
+    pub const fn fsk_deviation(mut self, fsk_deviation: u32) -> Self {
+        self.configuration.fsk_deviation = fsk_deviation;
+        self
+    }
+
+    /// Checks the configuration and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first [`ConfigurationError`] found.
+    pub fn build(self) -> Result<Configuration, ConfigurationError> {
+        let Self {
+            region,
+            model,
+            mut configuration,
+            encryption_key,
+        } = self;
+        let (region_min, region_max, _, region_max_power) = band_plan(&region);
+        let (model_min, model_max) = model.frequency_range();
+
+        for (min, max) in [(model_min, model_max), (region_min, region_max)] {
+            if !(min..=max).contains(&configuration.frequency) {
+                return Err(ConfigurationError::Frequency {
+                    frequency: configuration.frequency,
+                    min,
+                    max,
+                });
+            }
+        }
+
+        let max = region_max_power.min(MAX_TX_POWER);
+        if !(MIN_TX_POWER..=max).contains(&configuration.tx_power) {
+            return Err(ConfigurationError::TxPower {
+                tx_power: configuration.tx_power,
+                min: MIN_TX_POWER,
+                max,
+            });
+        }
+
+        if configuration.preamble_length < 2 {
+            return Err(ConfigurationError::PreambleLength(
+                configuration.preamble_length,
+            ));
+        }
+
+        let fsk = matches!(
+            configuration.working_mode,
+            at::commands::p2p::WorkingMode::FskP2P
+        );
+        match (&configuration.bandwidth, fsk) {
+            (at::commands::p2p::Bandwidth::Fsk(bandwidth), true) => {
+                if !(4_800..=467_000).contains(bandwidth) {
+                    return Err(ConfigurationError::FskBandwidth(*bandwidth));
+                }
+                if !(600..=300_000).contains(&configuration.fsk_bitrate) {
+                    return Err(ConfigurationError::FskBitrate(configuration.fsk_bitrate));
+                }
+                if !(600..=200_000).contains(&configuration.fsk_deviation) {
+                    return Err(ConfigurationError::FskDeviation(
+                        configuration.fsk_deviation,
+                    ));
+                }
+            }
+            (at::commands::p2p::Bandwidth::Fsk(_), false) | (_, true) => {
+                return Err(ConfigurationError::Bandwidth);
+            }
+            (_, false) => {
+                if !(5..=12).contains(&configuration.spreading_factor) {
+                    return Err(ConfigurationError::SpreadingFactor(
+                        configuration.spreading_factor,
+                    ));
+                }
+            }
+        }
+
+        if let Some(key) = encryption_key {
+            if key.len() != 16 || !key.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(ConfigurationError::EncryptionKey);
+            }
+            configuration.encrypted = Encrypted::True;
+            configuration.encryption_key = key.as_str().into();
+        }
+
+        Ok(configuration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::at::commands::p2p::{Bandwidth, WorkingMode};
+
+    #[test]
+    fn builds_valid_configurations() {
+        let configuration = Configuration::builder(Band::EU868, Model::Rak3172)
+            .frequency(869_525_000)
+            .spreading_factor(12)
+            .tx_power(16)
+            .encryption_key("0123456789abcdef")
+            .build()
+            .unwrap();
+        assert_eq!(configuration.frequency, 869_525_000);
+        assert_eq!(configuration.spreading_factor, 12);
+        assert!(matches!(configuration.encrypted, Encrypted::True));
+        assert_eq!(configuration.encryption_key, "0123456789abcdef");
+
+        let configuration = ConfigurationBuilder::new(Band::US915, Model::Rak4630)
+            .build()
+            .unwrap();
+        assert_eq!(configuration.frequency, 915_000_000);
+
+        let configuration = ConfigurationBuilder::new(Band::EU433, Model::Rak3172)
+            .working_mode(WorkingMode::FskP2P)
+            .bandwidth(Bandwidth::Fsk(125_000))
+            .fsk_bitrate(9_600)
+            .tx_power(10)
+            .build()
+            .unwrap();
+        assert_eq!(configuration.fsk_bitrate, 9_600);
+    }
+
+    #[test]
+    fn rejects_invalid_lora_parameters() {
+        let builder = ConfigurationBuilder::new(Band::EU868, Model::Rak3172);
+        assert_eq!(
+            builder.clone().spreading_factor(13).build().unwrap_err(),
+            ConfigurationError::SpreadingFactor(13)
+        );
+        assert_eq!(
+            builder.clone().spreading_factor(4).build().unwrap_err(),
+            ConfigurationError::SpreadingFactor(4)
+        );
+        assert_eq!(
+            builder.clone().preamble_length(1).build().unwrap_err(),
+            ConfigurationError::PreambleLength(1)
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .bandwidth(Bandwidth::Fsk(125_000))
+                .build()
+                .unwrap_err(),
+            ConfigurationError::Bandwidth
+        );
+        for key in ["0123", "0123456789abcdeg", "0123456789abcdef0"] {
+            assert_eq!(
+                builder.clone().encryption_key(key).build().unwrap_err(),
+                ConfigurationError::EncryptionKey
+            );
+        }
+    }
+
+    #[test]
+    fn checks_the_band_plan_and_the_module() {
+        assert_eq!(
+            ConfigurationBuilder::new(Band::EU868, Model::Rak3172)
+                .frequency(2_500_000_000)
+                .build()
+                .unwrap_err(),
+            ConfigurationError::Frequency {
+                frequency: 2_500_000_000,
+                min: 150_000_000,
+                max: 960_000_000,
+            }
+        );
+        assert_eq!(
+            ConfigurationBuilder::new(Band::EU868, Model::Rak3172)
+                .frequency(915_000_000)
+                .build()
+                .unwrap_err(),
+            ConfigurationError::Frequency {
+                frequency: 915_000_000,
+                min: 863_000_000,
+                max: 870_000_000,
+            }
+        );
+        // The region allows it, the RAK4630 does not.
+        assert!(ConfigurationBuilder::new(Band::EU433, Model::Rak3172)
+            .tx_power(12)
+            .build()
+            .is_ok());
+        assert_eq!(
+            ConfigurationBuilder::new(Band::EU433, Model::Rak4630)
+                .tx_power(12)
+                .build()
+                .unwrap_err(),
+            ConfigurationError::Frequency {
+                frequency: 433_375_000,
+                min: 863_000_000,
+                max: 928_000_000,
+            }
+        );
+        assert_eq!(
+            ConfigurationBuilder::new(Band::EU868, Model::Rak3172)
+                .tx_power(20)
+                .build()
+                .unwrap_err(),
+            ConfigurationError::TxPower {
+                tx_power: 20,
+                min: 5,
+                max: 16,
+            }
+        );
+        assert_eq!(
+            ConfigurationBuilder::new(Band::US915, Model::Rak4630)
+                .tx_power(30)
+                .build()
+                .unwrap_err(),
+            ConfigurationError::TxPower {
+                tx_power: 30,
+                min: 5,
+                max: 22,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_fsk_parameters() {
+        let builder = ConfigurationBuilder::new(Band::EU868, Model::Rak3172)
+            .working_mode(WorkingMode::FskP2P)
+            .bandwidth(Bandwidth::Fsk(125_000));
+        assert_eq!(
+            builder.clone().fsk_bitrate(500_000).build().unwrap_err(),
+            ConfigurationError::FskBitrate(500_000)
+        );
+        assert_eq!(
+            builder.clone().fsk_deviation(300_000).build().unwrap_err(),
+            ConfigurationError::FskDeviation(300_000)
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .bandwidth(Bandwidth::Fsk(1_000))
+                .build()
+                .unwrap_err(),
+            ConfigurationError::FskBandwidth(1_000)
+        );
+        assert_eq!(
+            builder
+                .bandwidth(Bandwidth::LoRa125KHz)
+                .build()
+                .unwrap_err(),
+            ConfigurationError::Bandwidth
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Identification of the module.
+
+use core::fmt;
+
+use crate::Model;
+
+/// Identification of a module, returned by [`Rui3Radio::device_info`].
+///
+/// [`Rui3Radio::device_info`]: crate::Rui3Radio::device_info
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// The serial number.
+    pub serial_number: atat::heapless::String<18>,
+    /// The firmware version, or `None` if `firmware` could not be parsed.
+    pub firmware_version: Option<FirmwareVersion>,
+    /// The firmware version as reported, e.g. `RUI_4.0.6_RAK3172-E`.
+    pub firmware: atat::heapless::String<128>,
+    /// The model, or `None` if `hardware_model` is not a known model.
+    pub model: Option<Model>,
+    /// The hardware model as reported, e.g. `rak3172`.
+    pub hardware_model: atat::heapless::String<32>,
+    /// The alias set with [`Rui3Radio::set_alias`].
+    ///
+    /// [`Rui3Radio::set_alias`]: crate::Rui3Radio::set_alias
+    pub alias: atat::heapless::String<16>,
+}
+
+/// Version of the RUI3 firmware, ordered from oldest to newest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    /// The major version.
+    pub major: u16,
+    /// The minor version.
+    pub minor: u16,
+    /// The patch version, 0 if not reported.
+    pub patch: u16,
+}
+
+impl FirmwareVersion {
+    /// Parses the version reported by `AT+VER`, e.g. `RUI_4.0.6_RAK3172-E` or
+    /// `4.0.6`.
+    ///
+    /// Returns `None` if it does not start with at least a major and a minor version.
+    #[must_use]
+    pub fn parse(version: &str) -> Option<Self> {
+        let version = version.trim();
+        let version = version.strip_prefix("RUI_").unwrap_or(version);
+        let end = version
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(version.len());
+
+        let mut numbers = version[..end].split('.').map(str::parse::<u16>);
+        let major = numbers.next()?.ok()?;
+        let minor = numbers.next()?.ok()?;
+        let patch = numbers.next().transpose().ok()?.unwrap_or(0);
+        Some(Self {
+            major,
+            minor,
+            patch,
+        })
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl Model {
+    /// Returns the model reported by `AT+HWMODEL`, e.g. `rak3172` or `rak4631`.
+    ///
+    /// Variants of a model, such as the `RAK3172-SiP`, are reported as the model.
+    #[must_use]
+    pub fn from_hardware_model(hardware_model: &str) -> Option<Self> {
+        let name = hardware_model.trim();
+        let is = |prefix: &str| {
+            name.get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+        };
+        if is("rak3172") {
+            Some(Self::Rak3172)
+        } else if is("rak4630") || is("rak4631") {
+            Some(Self::Rak4630)
+        } else if is("rak11720") || is("rak11722") {
+            Some(Self::Rak11720)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_firmware_versions() {
+        assert_eq!(
+            FirmwareVersion::parse("RUI_4.0.6_RAK3172-E"),
+            Some(FirmwareVersion {
+                major: 4,
+                minor: 0,
+                patch: 6
+            })
+        );
+        assert_eq!(
+            FirmwareVersion::parse("4.1"),
+            Some(FirmwareVersion {
+                major: 4,
+                minor: 1,
+                patch: 0
+            })
+        );
+        assert_eq!(FirmwareVersion::parse("RUI_mock"), None);
+        assert!(
+            FirmwareVersion::parse("RUI_4.0.6").unwrap()
+                < FirmwareVersion::parse("RUI_4.1.0").unwrap()
+        );
+    }
+
+    #[test]
+    fn parses_hardware_models() {
+        assert_eq!(
+            Model::from_hardware_model("rak3172-sip"),
+            Some(Model::Rak3172)
+        );
+        assert_eq!(Model::from_hardware_model("RAK4631"), Some(Model::Rak4630));
+        assert_eq!(
+            Model::from_hardware_model("rak11720"),
+            Some(Model::Rak11720)
+        );
+        assert_eq!(Model::from_hardware_model("rak"), None);
+    }
+}
@@ -0,0 +1,278 @@
+//! Errors reported by RUI3 modules.
+//!
+//! RUI3 firmware answers a failed command with one of a fixed set of status lines
+//! (`AT_PARAM_ERROR`, `AT_BUSY_ERROR`, ...) instead of the plain `ERROR` expected by
+//! [`atat`]. [`custom_error`] teaches the digester to recognise those lines, and
+//! [`Rui3Error`] maps each of them to its own variant.
+
+use core::fmt;
+
+use atat::digest::ParseError;
+
+/// An error reported by the RUI3 module or by the underlying AT client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rui3Error {
+    /// `AT_ERROR`: generic error.
+    Error,
+    /// `AT_PARAM_ERROR`: a parameter of the command is wrong.
+    ParamError,
+    /// `AT_BUSY_ERROR`: the module is busy, the command can be retried.
+    BusyError,
+    /// `AT_TEST_PARAM_OVERFLOW`: the parameter is too long.
+    TestParamOverflow,
+    /// `AT_NO_NETWORK_JOINED`: the module has not joined a network.
+    NoNetworkJoined,
+    /// `AT_RX_ERROR`: error detected during the reception of the command.
+    RxError,
+    /// `AT_DUTYCYCLE_RESTRICTED`: the duty cycle limit has been reached.
+    DutyCycleRestricted,
+    /// `AT_COMMAND_NOT_FOUND`: the firmware does not know the command.
+    CommandNotFound,
+    /// The received payload does not fit in the provided buffer.
+    BufferTooSmall,
+    /// The module reported `+EVT:JOIN_FAILED_*` after its last join attempt.
+    JoinFailed,
+    /// The module did not report the outcome of a join in time.
+    JoinTimeout,
+    /// The module did not report the end of a transmission in time.
+    TxTimeout,
+    /// Channel activity was detected at every listen-before-talk attempt.
+    ChannelBusy,
+    /// The command is not allowed while a transmission is in progress.
+    Transmitting,
+    /// The module did not answer in time after a reset.
+    NotResponding,
+    /// The queue of packets waiting to be sent is full.
+    QueueFull,
+    /// Transport or parsing error from the AT client.
+    Atat(atat::Error),
+}
+
+/// Status lines sent by RUI3 modules and the matching error.
+const ERROR_LINES: [(&[u8], Rui3Error); 8] = [
+    (b"AT_ERROR", Rui3Error::Error),
+    (b"AT_PARAM_ERROR", Rui3Error::ParamError),
+    (b"AT_BUSY_ERROR", Rui3Error::BusyError),
+    (b"AT_TEST_PARAM_OVERFLOW", Rui3Error::TestParamOverflow),
+    (b"AT_NO_NETWORK_JOINED", Rui3Error::NoNetworkJoined),
+    (b"AT_RX_ERROR", Rui3Error::RxError),
+    (b"AT_DUTYCYCLE_RESTRICTED", Rui3Error::DutyCycleRestricted),
+    (b"AT_COMMAND_NOT_FOUND", Rui3Error::CommandNotFound),
+];
+
+impl Rui3Error {
+    /// Maps a RUI3 status line (e.g. `AT_BUSY_ERROR`) to its error.
+    ///
+    /// Surrounding whitespace is ignored. Returns `None` if the line is not a known
+    /// RUI3 error.
+    #[must_use]
+    pub fn from_response(line: &[u8]) -> Option<Self> {
+        let line = line.trim_ascii();
+        ERROR_LINES
+            .iter()
+            .find(|(text, _)| *text == line)
+            .map(|(_, error)| error.clone())
+    }
+
+    /// Maps the error handed to [`atat::AtatCmd::parse`] to a `Rui3Error`.
+    ///
+    /// Lines recognised by [`custom_error`] reach the command as
+    /// [`atat::InternalError::Custom`] and are mapped to their own variant.
+    #[must_use]
+    pub fn from_internal(error: atat::InternalError) -> Self {
+        match error {
+            atat::InternalError::Custom(line) => {
+                Self::from_response(line).unwrap_or(Self::Atat(atat::Error::Custom))
+            }
+            error => Self::Atat(error.into()),
+        }
+    }
+
+    /// Returns `true` if sending the same command again may succeed.
+    #[must_use]
+    pub const fn is_retryable(&self) -> bool {
+        matches!(self, Self::BusyError | Self::Atat(atat::Error::Timeout))
+    }
+}
+
+impl From<atat::Error> for Rui3Error {
+    fn from(error: atat::Error) -> Self {
+        Self::Atat(error)
+    }
+}
+
+impl fmt::Display for Rui3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Atat(error) => write!(f, "AT client error: {error:?}"),
+            Self::BufferTooSmall => f.write_str("buffer too small for the received payload"),
+            Self::JoinFailed => f.write_str("failed to join the network"),
+            Self::JoinTimeout => f.write_str("join not completed in time"),
+            Self::TxTimeout => f.write_str("transmission not completed in time"),
+            Self::ChannelBusy => f.write_str("channel busy at every attempt"),
+            Self::Transmitting => f.write_str("transmission in progress"),
+            Self::NotResponding => f.write_str("module not responding after a reset"),
+            Self::QueueFull => f.write_str("transmission queue full"),
+            error => {
+                let line = ERROR_LINES
+                    .iter()
+                    .find(|(_, e)| e == error)
+                    .map_or(&b"AT_ERROR"[..], |(text, _)| *text);
+                // Error lines are plain ASCII.
+                f.write_str(core::str::from_utf8(line).unwrap_or("AT_ERROR"))
+            }
+        }
+    }
+}
+
+/// Custom error parser for [`atat::AtDigester`].
+///
+/// Recognises the RUI3 error status lines so that they complete the pending command
+/// instead of timing out.
+///
+/// # Example
+///
+/// ```compile_fail
+/// let digester = atat::AtDigester::<rui3_at::at::urc::URCMessages>::new()
+///     .with_custom_error(rui3_at::error::custom_error);
+/// ```
+///
+/// # Errors
+///
+/// Returns [`ParseError::Incomplete`] while a possible error line is still being
+/// received, and [`ParseError::NoMatch`] otherwise.
+pub fn custom_error(buf: &[u8]) -> Result<(&[u8], usize), ParseError> {
+    let start = buf
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .ok_or(ParseError::Incomplete)?;
+    let rest = &buf[start..];
+
+    let Some(end) = rest.windows(2).position(|w| w == b"\r\n") else {
+        // Wait for the rest of the line if it may still become an error line.
+        return if ERROR_LINES.iter().any(|(text, _)| text.starts_with(rest)) {
+            Err(ParseError::Incomplete)
+        } else {
+            Err(ParseError::NoMatch)
+        };
+    };
+
+    let line = &rest[..end];
+    if ERROR_LINES.iter().any(|(text, _)| *text == line) {
+        Ok((line, start + end + 2))
+    } else {
+        Err(ParseError::NoMatch)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Rui3Error {}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+    use crate::at::commands::{p2p::GetP2PFrequency, Checked, CheckedResponse};
+    use atat::AtatCmd;
+
+    #[test]
+    fn custom_error_matches_complete_error_lines() {
+        assert_eq!(
+            custom_error(b"AT_BUSY_ERROR\r\n"),
+            Ok((&b"AT_BUSY_ERROR"[..], 15))
+        );
+        // Leading whitespace is consumed with the line, what follows is left.
+        assert_eq!(
+            custom_error(b"\r\nAT_PARAM_ERROR\r\nOK\r\n"),
+            Ok((&b"AT_PARAM_ERROR"[..], 18))
+        );
+    }
+
+    #[test]
+    fn custom_error_waits_for_partial_error_lines() {
+        assert_eq!(custom_error(b""), Err(ParseError::Incomplete));
+        assert_eq!(custom_error(b"\r\n"), Err(ParseError::Incomplete));
+        assert_eq!(custom_error(b"AT_"), Err(ParseError::Incomplete));
+        assert_eq!(custom_error(b"AT_BUSY"), Err(ParseError::Incomplete));
+        // Complete but unterminated.
+        assert_eq!(custom_error(b"AT_BUSY_ERROR"), Err(ParseError::Incomplete));
+    }
+
+    #[test]
+    fn custom_error_ignores_other_lines() {
+        assert_eq!(custom_error(b"OK\r\n"), Err(ParseError::NoMatch));
+        assert_eq!(custom_error(b"+PFREQ=868000000"), Err(ParseError::NoMatch));
+        assert_eq!(
+            custom_error(b"AT_BUSY_ERRORS\r\n"),
+            Err(ParseError::NoMatch)
+        );
+        assert_eq!(custom_error(b"ERROR\r\n"), Err(ParseError::NoMatch));
+    }
+
+    #[test]
+    fn maps_each_error_line() {
+        for (line, error) in &ERROR_LINES {
+            assert_eq!(Rui3Error::from_response(line).as_ref(), Some(error));
+            assert_eq!(error.to_string().as_bytes(), *line);
+        }
+        assert_eq!(
+            Rui3Error::from_response(b" AT_RX_ERROR\r\n"),
+            Some(Rui3Error::RxError)
+        );
+        assert_eq!(Rui3Error::from_response(b"AT_UNKNOWN_ERROR"), None);
+        assert_eq!(Rui3Error::from_response(b"OK"), None);
+    }
+
+    #[test]
+    fn maps_internal_errors() {
+        assert_eq!(
+            Rui3Error::from_internal(atat::InternalError::Custom(b"AT_COMMAND_NOT_FOUND")),
+            Rui3Error::CommandNotFound
+        );
+        assert_eq!(
+            Rui3Error::from_internal(atat::InternalError::Custom(b"AT_UNKNOWN_ERROR")),
+            Rui3Error::Atat(atat::Error::Custom)
+        );
+        assert_eq!(
+            Rui3Error::from_internal(atat::InternalError::Timeout),
+            Rui3Error::Atat(atat::Error::Timeout)
+        );
+    }
+
+    #[test]
+    fn displays_errors() {
+        assert_eq!(
+            Rui3Error::DutyCycleRestricted.to_string(),
+            "AT_DUTYCYCLE_RESTRICTED"
+        );
+        assert_eq!(
+            Rui3Error::TxTimeout.to_string(),
+            "transmission not completed in time"
+        );
+        assert_eq!(
+            Rui3Error::Atat(atat::Error::Timeout).to_string(),
+            "AT client error: Timeout"
+        );
+    }
+
+    #[test]
+    fn checked_keeps_timeouts_for_the_client() {
+        let command = Checked(&GetP2PFrequency {});
+
+        // Timeouts go through the client, which may retry the command.
+        assert!(matches!(
+            command.parse(Err(atat::InternalError::Timeout)),
+            Err(atat::Error::Timeout)
+        ));
+        // Error lines are answers of the module.
+        assert!(matches!(
+            command.parse(Err(atat::InternalError::Custom(b"AT_BUSY_ERROR"))),
+            Ok(CheckedResponse(Err(Rui3Error::BusyError)))
+        ));
+        assert!(matches!(
+            command.parse(Err(atat::InternalError::Error)),
+            Ok(CheckedResponse(Err(Rui3Error::Atat(atat::Error::Error))))
+        ));
+    }
+}
@@ -22,19 +22,53 @@
 //!
 //!
 //! ```
+//!
+//! # Async
+//!
+//! With the `async` feature, [`AsyncRui3Radio`] drives the module from an async
+//! executor such as Embassy. `atat` 0.18 only has the blocking [`atat::AtatClient`],
+//! so the async client is described by the crate's own [`asynch::AsyncClient`]
+//! trait, along with [`asynch::UrcSubscription`] and [`asynch::Timer`]: implement
+//! them on top of the async AT client, URC channel and timer of the executor.
 
 #![warn(clippy::pedantic)]
 #![warn(clippy::nursery)]
 #![warn(clippy::cargo)]
 #![warn(rustdoc::all)]
 // #![warn(missing_docs)]
-//#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+use core::fmt::Write;
+
+use apply::{ApplyStep, ConfigurationPlan};
 use at::commands::p2p::Encrypted;
+use at::commands::{Checked, CheckedResponse};
 
 extern crate alloc;
 
+mod airtime;
+mod apply;
+#[cfg(feature = "async")]
+pub mod asynch;
 pub mod at;
+mod builder;
+mod device;
+pub mod error;
+mod listener;
+pub mod lorawan;
+mod profile;
+mod scheduler;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+#[cfg(feature = "async")]
+pub use asynch::AsyncRui3Radio;
+pub use builder::{ConfigurationBuilder, ConfigurationError, Model};
+pub use device::{DeviceInfo, FirmwareVersion};
+pub use error::Rui3Error;
+pub use listener::Listener;
+pub use profile::{Profile, Profiles};
+pub use scheduler::Scheduler;
 
 /// A struct to define the radio client.
 pub struct Rui3Radio<C>
@@ -42,36 +76,199 @@ where
     C: atat::AtatClient,
 {
     client: C,
-    rssi: i16,
-    snr: i16,
+    /// Signal quality of the last received packets.
+    link_quality: atat::heapless::HistoryBuffer<LinkQuality, LINK_QUALITY_HISTORY_LEN>,
+    /// Last configuration applied to or read from the module.
+    configuration: Configuration,
+    /// Whether `configuration` mirrors the module, or must be read first.
+    configuration_known: bool,
+    /// Last configuration passed to [`Rui3Radio::configure`], applied again after
+    /// a reset.
+    requested_configuration: Option<Configuration>,
+    /// Whether the firmware knows `AT+P2P`, cleared when it answers
+    /// `AT_COMMAND_NOT_FOUND`.
+    combined_p2p: bool,
+    /// Time allowed on top of the time on air for the module to report a transmission.
+    tx_timeout_margin: fugit::MillisDurationU32,
+    /// Whether the module checks the channel before transmitting.
+    channel_activity_detection: bool,
+    /// State of the generator drawing listen-before-talk backoffs.
+    backoff_seed: u32,
+    /// What the module is doing, from the commands sent and the events received.
+    state: RadioState,
+    /// Packets received while waiting for other events, with their RSSI and SNR.
+    kept_packets: atat::heapless::Deque<(i16, i16, at::urc::Payload), KEPT_PACKETS_LEN>,
+}
+
+/// What the module is doing, as tracked by [`Rui3Radio::state`] and
+/// [`Scheduler::state`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RadioState {
+    /// Neither receiving nor transmitting.
+    Idle,
+    /// Receiving in continuous mode.
+    Rx,
+    /// Transmitting a packet.
+    Tx,
+    /// Receiving until the end of a window.
+    RxWindow,
+    /// Receiving until the first packet.
+    RxOnePacket,
+}
+
+/// A transmission started by [`Rui3Radio::start_send`].
+///
+/// Holds the instant after which [`Rui3Radio::poll_send`] gives up waiting.
+#[derive(Clone, Copy, Debug)]
+pub struct PendingSend<const TIMER_HZ: u32> {
+    deadline: fugit::TimerInstantU32<TIMER_HZ>,
+}
+
+/// Number of received packets kept in the link quality history.
+pub const LINK_QUALITY_HISTORY_LEN: usize = 16;
+
+/// Number of packets received while waiting for the end of a transmission or a
+/// `LoRaWAN` event, kept for the next receive.
+pub const KEPT_PACKETS_LEN: usize = 4;
+
+/// Time allowed for the module to answer again after a reset.
+const BOOT_TIMEOUT: fugit::MillisDurationU32 = fugit::MillisDurationU32::millis(5_000);
+/// First and longest delays between two `AT` while waiting for the module to boot.
+const BOOT_POLL_DELAYS: (fugit::MillisDurationU32, fugit::MillisDurationU32) = (
+    fugit::MillisDurationU32::millis(10),
+    fugit::MillisDurationU32::millis(500),
+);
+
+/// A packet received by the module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReceivedPacket<const TIMER_HZ: u32> {
+    /// The received data.
+    pub payload: at::urc::Payload,
+    /// The 'Received signal strength indicator' (RSSI) of the packet.
+    pub rssi: i16,
+    /// The 'Signal to noise ratio' (SNR) of the packet.
+    pub snr: i16,
+    /// The instant the packet was received at, according to the clock passed to the
+    /// receive function.
+    pub timestamp: fugit::TimerInstantU32<TIMER_HZ>,
+}
+
+impl<const TIMER_HZ: u32> Default for ReceivedPacket<TIMER_HZ> {
+    fn default() -> Self {
+        Self {
+            payload: at::urc::Payload::new(),
+            rssi: 0,
+            snr: 0,
+            timestamp: fugit::TimerInstantU32::from_ticks(0),
+        }
+    }
+}
+
+/// A packet received by the module into a caller-provided buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PacketInfo<const TIMER_HZ: u32> {
+    /// The number of bytes written to the buffer.
+    pub len: usize,
+    /// The 'Received signal strength indicator' (RSSI) of the packet.
+    pub rssi: i16,
+    /// The 'Signal to noise ratio' (SNR) of the packet.
+    pub snr: i16,
+    /// The instant the packet was received at, according to the clock passed to the
+    /// receive function.
+    pub timestamp: fugit::TimerInstantU32<TIMER_HZ>,
+}
+
+/// Signal quality of a received packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkQuality {
+    /// The 'Received signal strength indicator' (RSSI) in dBm.
+    pub rssi: i16,
+    /// The 'Signal to noise ratio' (SNR) in dB.
+    pub snr: i16,
 }
 
 /// A struct to define the radio configuration.
+///
+/// With the `serde` feature, the enumerations are stored as their values in AT
+/// commands, and the encryption as a boolean.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Configuration {
     /// The working mode of the radio.
+    #[cfg_attr(feature = "serde", serde(with = "profile::fields::working_mode"))]
     pub working_mode: at::commands::p2p::WorkingMode,
     /// The frequency used.
     pub frequency: u32,
     /// The spreading factor used.
     pub spreading_factor: u8,
     /// The bandwidth used.
+    #[cfg_attr(feature = "serde", serde(with = "profile::fields::bandwidth"))]
     pub bandwidth: at::commands::p2p::Bandwidth,
     /// The code rate used.
+    #[cfg_attr(feature = "serde", serde(with = "profile::fields::code_rate"))]
     pub code_rate: at::commands::p2p::CodeRate,
     /// The preamble length used.
     pub preamble_length: u16,
     /// The TX power used.
     pub tx_power: u8,
     /// Whether the encryption is enabled or not.
+    #[cfg_attr(feature = "serde", serde(with = "profile::fields::encrypted"))]
     pub encrypted: Encrypted,
     /// The encryption key used.
+    #[cfg_attr(feature = "serde", serde(with = "profile::fields::encryption_key"))]
     pub encryption_key: atat::heapless::String<16>,
+    /// The FSK bitrate in bits per second, used in FSK P2P mode.
+    pub fsk_bitrate: u32,
+    /// The FSK frequency deviation in Hz, used in FSK P2P mode.
+    pub fsk_deviation: u32,
+    /// The sync word used.
+    pub sync_word: u16,
+    /// Whether the IQ inversion is enabled or not.
+    pub iq_inversion: bool,
+    /// The symbol timeout used, 0 to disable it.
+    pub symbol_timeout: u8,
+}
+
+/// A parameter of a [`Configuration`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigurationParameter {
+    /// The working mode.
+    WorkingMode,
+    /// The frequency.
+    Frequency,
+    /// The spreading factor.
+    SpreadingFactor,
+    /// The bandwidth.
+    Bandwidth,
+    /// The code rate.
+    CodeRate,
+    /// The preamble length.
+    PreambleLength,
+    /// The TX power.
+    TxPower,
+    /// The FSK bitrate.
+    FskBitrate,
+    /// The FSK frequency deviation.
+    FskDeviation,
+    /// The encryption key.
+    EncryptionKey,
+    /// The encryption mode.
+    Encryption,
+    /// The sync word.
+    SyncWord,
+    /// The IQ inversion.
+    IqInversion,
+    /// The symbol timeout.
+    SymbolTimeout,
 }
 
+/// The parameters changed by [`Rui3Radio::configure`], in the order they were set.
+pub type ConfigurationChanges = atat::heapless::Vec<ConfigurationParameter, 14>;
+
 /// Default trait implementation for Configuration.
 impl Default for Configuration {
     fn default() -> Self {
-        println!("Default configuration used");
+        log::debug!("Default configuration used");
         Self {
             working_mode: at::commands::p2p::WorkingMode::LoRaP2P,
             frequency: 868_000_000,
@@ -82,10 +279,113 @@ impl Default for Configuration {
             tx_power: 14,
             encrypted: Encrypted::False,
             encryption_key: "".into(),
+            fsk_bitrate: 50_000,
+            fsk_deviation: 5_000,
+            sync_word: 0x3444,
+            iq_inversion: false,
+            symbol_timeout: 0,
         }
     }
 }
 
+impl Configuration {
+    /// Returns the parameters of this configuration which differ from `current`,
+    /// in the order [`Rui3Radio::configure`] sets them.
+    ///
+    /// An empty encryption key keeps the current one, and keys are compared
+    /// case-insensitively, as the module reports them in uppercase. The spreading
+    /// factor and the code rate are ignored in FSK P2P mode, the FSK bitrate and
+    /// deviation in the other modes.
+    ///
+    /// # Arguments
+    ///
+    /// * `current` - The configuration to compare against, such as the one
+    ///   returned by [`Rui3Radio::read_configuration`].
+    #[allow(missing_doc_code_examples)]
+    #[must_use]
+    pub fn diff(&self, current: &Self) -> ConfigurationChanges {
+        self.parameters(Some(current))
+    }
+
+    /// Returns the parameters of this configuration which differ from `current`,
+    /// like [`diff`], or every parameter it sets if `current` is unknown.
+    ///
+    /// [`diff`]: #method.diff
+    pub(crate) fn parameters(&self, current: Option<&Self>) -> ConfigurationChanges {
+        let fsk = self.working_mode == at::commands::p2p::WorkingMode::FskP2P;
+        let differs = |changed: fn(&Self, &Self) -> bool| {
+            current.is_none_or(|current| changed(self, current))
+        };
+        let differing = [
+            (
+                ConfigurationParameter::WorkingMode,
+                differs(|new, current| new.working_mode != current.working_mode),
+            ),
+            (
+                ConfigurationParameter::Frequency,
+                differs(|new, current| new.frequency != current.frequency),
+            ),
+            (
+                ConfigurationParameter::SpreadingFactor,
+                !fsk && differs(|new, current| new.spreading_factor != current.spreading_factor),
+            ),
+            (
+                ConfigurationParameter::Bandwidth,
+                differs(|new, current| new.bandwidth != current.bandwidth),
+            ),
+            (
+                ConfigurationParameter::CodeRate,
+                !fsk && differs(|new, current| new.code_rate != current.code_rate),
+            ),
+            (
+                ConfigurationParameter::PreambleLength,
+                differs(|new, current| new.preamble_length != current.preamble_length),
+            ),
+            (
+                ConfigurationParameter::TxPower,
+                differs(|new, current| new.tx_power != current.tx_power),
+            ),
+            (
+                ConfigurationParameter::FskBitrate,
+                fsk && differs(|new, current| new.fsk_bitrate != current.fsk_bitrate),
+            ),
+            (
+                ConfigurationParameter::FskDeviation,
+                fsk && differs(|new, current| new.fsk_deviation != current.fsk_deviation),
+            ),
+            (
+                ConfigurationParameter::EncryptionKey,
+                !self.encryption_key.is_empty()
+                    && differs(|new, current| {
+                        !new.encryption_key
+                            .eq_ignore_ascii_case(&current.encryption_key)
+                    }),
+            ),
+            (
+                ConfigurationParameter::Encryption,
+                differs(|new, current| new.encrypted != current.encrypted),
+            ),
+            (
+                ConfigurationParameter::SyncWord,
+                differs(|new, current| new.sync_word != current.sync_word),
+            ),
+            (
+                ConfigurationParameter::IqInversion,
+                differs(|new, current| new.iq_inversion != current.iq_inversion),
+            ),
+            (
+                ConfigurationParameter::SymbolTimeout,
+                differs(|new, current| new.symbol_timeout != current.symbol_timeout),
+            ),
+        ];
+        differing
+            .iter()
+            .filter(|(_, differs)| *differs)
+            .map(|(parameter, _)| *parameter)
+            .collect()
+    }
+}
+
 impl<C> Rui3Radio<C>
 where
     C: atat::AtatClient,
@@ -102,440 +402,705 @@ where
     /// let (mut client, ingress) = ClientBuilder::new(tx, timer, atat::Config::new(atat::Mode::Timeout)).build(queues);
     /// let radio_client = Rui3Radio::new(client);
     /// ```
-    pub const fn new(client: C) -> Self {
+    pub fn new(client: C) -> Self {
         Self {
             client,
-            rssi: 0,
-            snr: 0,
+            link_quality: atat::heapless::HistoryBuffer::new(),
+            configuration: Configuration::default(),
+            configuration_known: false,
+            requested_configuration: None,
+            combined_p2p: true,
+            tx_timeout_margin: fugit::MillisDurationU32::millis(500),
+            channel_activity_detection: false,
+            backoff_seed: 0x9E37_79B9,
+            state: RadioState::Idle,
+            kept_packets: atat::heapless::Deque::new(),
         }
     }
 
     /// Converts data to hex and sends it.
     ///
     /// Takes as parameter a slice of u8 and converts it to a hex string.
-    /// Then temporarily disables RX and sends the data.
+    /// Then temporarily disables RX, sends the data and waits for `+EVT:TXP2P DONE`.
     /// Finally re-enables RX.
     ///
+    /// Gives up if `+EVT:TXP2P DONE` is not received within the time on air of the
+    /// packet plus the margin set with [`set_tx_timeout_margin`]. The time on air is
+    /// computed from the last configuration applied to or read from the module.
+    ///
+    /// Packets received while waiting are kept, up to [`KEPT_PACKETS_LEN`], and
+    /// returned by the next [`receive`] or [`poll`].
+    ///
+    /// [`set_tx_timeout_margin`]: #method.set_tx_timeout_margin
+    /// [`receive`]: #method.receive
+    /// [`poll`]: #method.poll
+    ///
     /// # Arguments
     ///
     /// * `data` - The data to send.
+    /// * `clock` - The clock used to measure the timeout.
     ///
     /// # Example
     ///
     /// ```compile_fail
     /// let data = [5; 4];
-    /// radio_client.send(&data)?;
+    /// radio_client.send(&data, &mut clock)?;
     /// ```
     ///
     /// # Errors
     ///
-    /// TODO
+    /// Returns [`Rui3Error::TxTimeout`] if the transmission is not reported in time,
+    /// [`Rui3Error::ParamError`] if `data` is too long, [`Rui3Error::ChannelBusy`] if
+    /// channel activity detection is enabled and the channel is busy, or another
+    /// [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub fn send<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        data: &[u8],
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        let pending = self.start_send(data, clock)?;
+        loop {
+            match self.poll_send(&pending, clock) {
+                Err(nb::Error::WouldBlock) => {}
+                result => return result,
+            }
+        }
+    }
+
+    /// Starts sending data without waiting for the end of the transmission.
+    ///
+    /// Disables RX and sends the data. The transmission must then be polled with
+    /// [`poll_send`] until it completes.
     ///
-    /// # Panics
+    /// [`poll_send`]: #method.poll_send
     ///
-    /// TODO
-    pub fn send(&mut self, data: &[u8]) -> Result<(), nb::Error<atat::Error>> {
-        // Convert each byte of data to a hex string.
-        let mut string_result: atat::heapless::String<500> = "".into();
-        // Add \r\n to the end of the string.
-
-        data.iter()
-            .map(|b| alloc::format!("{b:02X}"))
-            .for_each(|s| string_result.push_str(&s).unwrap());
-
-        string_result.push_str("\r\n").unwrap();
-        let send_command = at::commands::p2p::SendData {
-            payload: string_result,
-        }; // TODO: this loop here.
-           // Disable RX.
-        self.client.send(&at::commands::p2p::ReceiveData {
-            window: at::commands::p2p::ReceiveWindow::StopListening,
-        })?;
+    /// # Arguments
+    ///
+    /// * `data` - The data to send.
+    /// * `clock` - The clock used to measure the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// let pending = radio_client.start_send(&data, &mut clock)?;
+    /// nb::block!(radio_client.poll_send(&pending, &mut clock))?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::ParamError`] if `data` is too long, or another
+    /// [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub fn start_send<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        data: &[u8],
+        clock: &mut CLK,
+    ) -> Result<PendingSend<TIMER_HZ>, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        self.start_transmission(data)?;
 
-        // Send data.
-        self.client.send_retry(&send_command)?;
-        println!("Sending data: {:?}", data);
-        // Re-enable RX.
-        self.client.send_retry(&at::commands::p2p::ReceiveData {
-            window: at::commands::p2p::ReceiveWindow::Continuous,
-        })?;
-        Ok(())
+        let timeout = self.configuration.time_on_air(data.len()) + self.tx_timeout_margin.convert();
+        log::debug!("Waiting up to {timeout} for the transmission");
+        Ok(PendingSend {
+            deadline: clock.now() + timeout.convert(),
+        })
     }
 
-    /// Receives data in countinous mode.
+    /// Polls a transmission started with [`start_send`].
     ///
-    /// Checks for URCs in a loop and returns the data as a vector of u8.
-    /// If configured in RX mode, any new values of AT+PRECV will not be accepted.
-    /// To stop receiving, send AT+PRECV=0 via TODO
+    /// Returns [`nb::Error::WouldBlock`] until the module reports the end of the
+    /// transmission, then re-enables RX.
+    ///
+    /// [`start_send`]: #method.start_send
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The transmission returned by [`start_send`].
+    /// * `clock` - The clock passed to [`start_send`].
     ///
     /// # Example
     ///
     /// ```compile_fail
-    /// let data = radio_client.receive()?;
+    /// match radio_client.poll_send(&pending, &mut clock) {
+    ///     Err(nb::Error::WouldBlock) => do_other_work(),
+    ///     result => result?,
+    /// }
     /// ```
     ///
     /// # Errors
     ///
-    /// TODO
+    /// Returns [`Rui3Error::TxTimeout`] if the transmission is not reported in time,
+    /// [`Rui3Error::ChannelBusy`] if channel activity detection is enabled and the
+    /// channel is busy, or another [`Rui3Error`] if the module rejects a command or
+    /// the AT client fails.
+    pub fn poll_send<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        pending: &PendingSend<TIMER_HZ>,
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        if self.check_tx_done()? {
+            return self.finish_transmission();
+        }
+        if clock.now() >= pending.deadline {
+            return Err(self.abandon_transmission());
+        }
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Sends data once the channel is free, trying again after a random backoff while
+    /// it is busy.
+    ///
+    /// Enables channel activity detection for the attempts if needed, and disables it
+    /// again afterwards: before each transmission the module listens for `LoRa`
+    /// preambles, and only sends if it detects none. When
+    /// the channel is busy, waits a random time between half of `backoff` and
+    /// `backoff` before trying again, so that modules sharing the frequency do not
+    /// retry in lockstep. Each attempt waits for the end of the transmission like
+    /// [`send`].
     ///
-    /// # Panics
+    /// [`send`]: #method.send
     ///
-    /// TODO
-    pub fn receive(&mut self) -> Result<alloc::vec::Vec<u8>, nb::Error<atat::Error>> {
-        // Recieve is blocking until data is received.
-        let receive_command = at::commands::p2p::ReceiveData {
-            window: at::commands::p2p::ReceiveWindow::StopListening,
+    /// # Arguments
+    ///
+    /// * `data` - The data to send.
+    /// * `max_attempts` - The number of times the channel is checked, at least 1.
+    /// * `backoff` - The longest time to wait after finding the channel busy.
+    /// * `clock` - The clock used to measure the backoff and the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// radio_client.set_backoff_seed(device_serial);
+    /// radio_client.send_with_lbt(&data, 5, fugit::MillisDurationU32::millis(200), &mut clock)?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::ChannelBusy`] if the channel is busy at every attempt, or
+    /// the errors of [`send`].
+    pub fn send_with_lbt<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        data: &[u8],
+        max_attempts: u8,
+        backoff: fugit::MillisDurationU32,
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        let enabled = self.channel_activity_detection;
+        if !enabled {
+            self.set_channel_activity_detection(true)?;
+        }
+
+        let mut attempt = 1;
+        let result = loop {
+            match self.send(data, clock) {
+                Err(nb::Error::Other(Rui3Error::ChannelBusy)) if attempt < max_attempts => {
+                    let delay = self.next_backoff(backoff, clock.now().ticks());
+                    log::debug!("Channel busy at attempt {attempt}, waiting {delay}");
+                    wait(clock, delay);
+                    attempt += 1;
+                }
+                result => break result,
+            }
         };
 
-        // Enable RX.
-        self.client.send(&receive_command)?;
+        if enabled {
+            return result;
+        }
+        // Restore the setting on failure too, the error of the send comes first.
+        let restored = self.set_channel_activity_detection(false);
+        result.and(restored)
+    }
 
-        let receive_command = at::commands::p2p::ReceiveData {
-            window: at::commands::p2p::ReceiveWindow::Continuous,
-        };
+    #[allow(missing_doc_code_examples)]
+    /// Seeds the generator drawing the backoffs of [`send_with_lbt`].
+    ///
+    /// The clock is mixed in at each draw, but modules started together should
+    /// still be given different seeds, e.g. derived from their serial number.
+    ///
+    /// [`send_with_lbt`]: #method.send_with_lbt
+    pub fn set_backoff_seed(&mut self, seed: u32) {
+        // Xorshift never leaves zero.
+        self.backoff_seed = seed.max(1);
+    }
 
-        // Enable RX.
-        self.client.send(&receive_command)?;
+    #[allow(missing_doc_code_examples)]
+    /// Sets the time allowed on top of the time on air for the module to report the
+    /// end of a transmission.
+    ///
+    /// Defaults to 500 ms, which covers the serial link and the module latency.
+    pub const fn set_tx_timeout_margin(&mut self, margin: fugit::MillisDurationU32) {
+        self.tx_timeout_margin = margin;
+    }
 
-        loop {
-            // Check for URCs in loop.
-            let check_urc = self.client.check_urc::<at::urc::URCMessages>();
-
-            match check_urc {
-                Some(at::urc::URCMessages::PeerToPeerData(hex_data)) => {
-                    // let data = decode(hex_data).unwrap();
-
-                    // if hex_data.len() % 2 != 0 {
-                    //     Err
-                    // }
-
-                    let hex_data = alloc::string::String::from_utf8(hex_data).unwrap();
-                    let mut data = alloc::vec::Vec::new();
-                    for i in (0..hex_data.len()).step_by(2) {
-                        let byte = u8::from_str_radix(&hex_data[i..i + 2], 16).unwrap();
-                        data.push(byte);
-                    }
+    /// Receives data in continuous mode.
+    ///
+    /// Checks for URCs in a loop and returns the first received packet, with its
+    /// signal quality and the instant it was received at.
+    /// If configured in RX mode, any new values of AT+PRECV will not be accepted.
+    /// The module keeps receiving afterwards, until [`set_receiving_window`] is
+    /// called with [`ReceiveWindow::StopListening`].
+    ///
+    /// See [`listen`] to receive several packets without restarting RX for each one,
+    /// stopping it with [`Listener::stop`], and [`receive_into`] for a variant that
+    /// copies the payload to a buffer.
+    ///
+    /// [`listen`]: #method.listen
+    /// [`receive_into`]: #method.receive_into
+    /// [`set_receiving_window`]: #method.set_receiving_window
+    /// [`ReceiveWindow::StopListening`]: at::commands::p2p::ReceiveWindow::StopListening
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The clock used to timestamp the packet.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// let packet = radio_client.receive(&mut clock)?;
+    /// let data = &packet.payload;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub fn receive<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        clock: &mut CLK,
+    ) -> Result<ReceivedPacket<TIMER_HZ>, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        // Recieve is blocking until data is received.
+        self.restart_continuous_rx()?;
+        Ok(self.wait_for_packet(clock))
+    }
 
-                    // Return as a vector of u8.
-                    return Ok(data);
-                }
-                Some(at::urc::URCMessages::PeerToPeerInfo { rssi, snr }) => {
-                    self.rssi = rssi;
-                    self.snr = snr;
-                }
-                Some(at::urc::URCMessages::PeerToPeerMessage { rssi, snr, data }) => {}
-                None => {}
-            }
-        }
+    /// Receives data in continuous mode into a caller-provided buffer.
+    ///
+    /// Same as [`receive`], copying the payload to `buf`.
+    /// Returns the number of bytes written to `buf`, with the signal quality and the
+    /// instant the packet was received at.
+    ///
+    /// [`receive`]: #method.receive
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - The buffer the payload is copied to.
+    /// * `clock` - The clock used to timestamp the packet.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// let mut buf = [0; at::urc::MAX_PAYLOAD_LEN];
+    /// let info = radio_client.receive_into(&mut buf, &mut clock)?;
+    /// let data = &buf[..info.len];
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::BufferTooSmall`] if the payload does not fit in `buf`, or
+    /// another [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub fn receive_into<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        buf: &mut [u8],
+        clock: &mut CLK,
+    ) -> Result<PacketInfo<TIMER_HZ>, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        let packet = self.receive(clock)?;
+        copy_packet(&packet, buf)
+    }
+
+    /// Listens in continuous mode and returns the received packets as they come.
+    ///
+    /// RX is enabled once, and stays enabled until the returned [`Listener`] is
+    /// stopped or dropped. Packets received between two calls to the listener are
+    /// not lost.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The clock used to timestamp the packets.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// for packet in radio_client.listen(&mut clock)? {
+    ///     log::info!("{:?} (RSSI {})", packet.payload, packet.rssi);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub fn listen<'a, CLK, const TIMER_HZ: u32>(
+        &'a mut self,
+        clock: &'a mut CLK,
+    ) -> Result<Listener<'a, C, CLK, TIMER_HZ>, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        Listener::start(self, clock)
     }
 
     /// Receives data in any mode.
     ///
-    /// Takes as parameter a `ReceiveWindow` enum and returns the data as a vector of u8.
+    /// Takes as parameter a `ReceiveWindow` enum and returns the received packets.
     /// Possible values are:
-    /// * `ReceiveWindow::Milliseconds(millis)` - Receives data for a certain amount of time.
+    /// * `ReceiveWindow::Milliseconds(millis)` - Receives every packet sent during
+    ///   `millis`, until the window elapses or the module reports the end of the window.
     /// * `ReceiveWindow::OnePacket` - Receives data for one packet.
-    /// * `ReceiveWindow::Continuous` - Receives data in continuous mode.
+    /// * `ReceiveWindow::Continuous` - Receives data in continuous mode, returning the
+    ///   first packet.
     /// * `ReceiveWindow::StopListening` - Stops listening.
     ///
     /// # Arguments
     ///
     /// * `receiving_window` - The receiving window.
+    /// * `clock` - The clock used to measure the window.
     ///
     /// # Example
     ///
     /// ```compile_fail
-    /// let data = radio_client.receive_explicit(at::commands::p2p::ReceiveWindow::Milliseconds(millis))?;
+    /// let packets = radio_client.receive_explicit(&at::commands::p2p::ReceiveWindow::Milliseconds(millis), &mut clock)?;
+    /// for packet in packets {
+    ///     println!("{:?} (RSSI {}, SNR {})", packet.payload, packet.rssi, packet.snr);
+    /// }
     /// ```
     ///
     /// ```compile_fail
-    /// let data = radio_client.receive_explicit(at::commands::p2p::ReceiveWindow::OnePacket)?;
+    /// let packets = radio_client.receive_explicit(&at::commands::p2p::ReceiveWindow::OnePacket, &mut clock)?;
     /// ```
     ///
     /// # Errors
     ///
-    /// TODO
+    /// Returns a [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub fn receive_explicit<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        receiving_window: &at::commands::p2p::ReceiveWindow,
+        clock: &mut CLK,
+    ) -> Result<alloc::vec::Vec<ReceivedPacket<TIMER_HZ>>, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        let mut packets = alloc::vec::Vec::new();
+        self.receive_explicit_packets(receiving_window, clock, |packet| packets.push(packet))?;
+        Ok(packets)
+    }
+
+    /// Receives data in any mode into a caller-provided buffer.
+    ///
+    /// Same as [`receive_explicit`], without allocating.
+    /// Returns the number of packets written to `packets`; packets received once
+    /// `packets` is full are dropped.
+    ///
+    /// [`receive_explicit`]: #method.receive_explicit
+    ///
+    /// # Arguments
+    ///
+    /// * `receiving_window` - The receiving window.
+    /// * `clock` - The clock used to measure the window.
+    /// * `packets` - The buffer the packets are copied to.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// let mut packets: [ReceivedPacket<1_000_000>; 4] = Default::default();
+    /// let count = radio_client.receive_explicit_into(&at::commands::p2p::ReceiveWindow::Milliseconds(millis), &mut clock, &mut packets)?;
+    /// let packets = &packets[..count];
+    /// ```
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// TODO
-    pub fn receive_explicit(
+    /// Returns a [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub fn receive_explicit_into<CLK, const TIMER_HZ: u32>(
         &mut self,
         receiving_window: &at::commands::p2p::ReceiveWindow,
-    ) -> Result<alloc::vec::Vec<u8>, nb::Error<atat::Error>> {
-        match receiving_window {
-            at::commands::p2p::ReceiveWindow::Milliseconds(millis) => {
-                // Enable RX
-                self.client.send(&at::commands::p2p::ReceiveData {
-                    window: at::commands::p2p::ReceiveWindow::Milliseconds(*millis),
-                })?;
-
-                // TODO: find how to wait for a certain amount of time.
-
-                let data: alloc::vec::Vec<u8> = alloc::vec![];
-                Ok(data)
-            }
-            at::commands::p2p::ReceiveWindow::OnePacket => {
-                // Enable RX
-                self.client.send(&at::commands::p2p::ReceiveData {
-                    window: at::commands::p2p::ReceiveWindow::OnePacket,
-                })?;
-
-                loop {
-                    // Check for URCs in loop.
-                    let check_urc = self.client.check_urc::<at::urc::URCMessages>();
-
-                    match check_urc {
-                        Some(at::urc::URCMessages::PeerToPeerData(hex_data)) => {
-                            // let data = decode(hex_data).unwrap();
-
-                            // if hex_data.len() % 2 != 0 {
-                            //     Err
-                            // }
-
-                            let hex_data = alloc::string::String::from_utf8(hex_data).unwrap();
-                            let mut data = alloc::vec::Vec::new();
-                            for i in (0..hex_data.len()).step_by(2) {
-                                let byte = u8::from_str_radix(&hex_data[i..i + 2], 16).unwrap();
-                                data.push(byte);
-                            }
-
-                            // Return as a vector of u8.
-                            return Ok(data);
-                        }
-                        Some(at::urc::URCMessages::PeerToPeerInfo { rssi, snr }) => {
-                            self.rssi = rssi;
-                            self.snr = snr;
-                        }
-                        Some(at::urc::URCMessages::PeerToPeerMessage { rssi, snr, data }) => {}
-                        None => {
-                            continue;
-                        }
-                    }
-                }
+        clock: &mut CLK,
+        packets: &mut [ReceivedPacket<TIMER_HZ>],
+    ) -> Result<usize, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        let mut count = 0;
+        self.receive_explicit_packets(receiving_window, clock, |packet| {
+            if let Some(slot) = packets.get_mut(count) {
+                *slot = packet;
+                count += 1;
+            } else {
+                log::warn!("Dropping packet, the buffer is full");
             }
-            at::commands::p2p::ReceiveWindow::Continuous => self.receive(),
-            at::commands::p2p::ReceiveWindow::StopListening => {
-                // Disable RX
-                self.client.send(&at::commands::p2p::ReceiveData {
-                    window: at::commands::p2p::ReceiveWindow::StopListening,
-                })?;
-                Ok(alloc::vec![])
-            }
-        }
+        })?;
+        Ok(count)
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Sets the receiving window.
+    ///
+    /// RX is stopped first if the module is receiving. Returns
+    /// [`Rui3Error::Transmitting`] while a transmission is in progress.
     pub fn set_receiving_window(
         &mut self,
         receiving_window: at::commands::p2p::ReceiveWindow,
-    ) -> Result<(), nb::Error<atat::Error>> {
-        self.client.send(&at::commands::p2p::ReceiveData {
-            window: receiving_window,
-        })?;
-        Ok(())
+    ) -> Result<(), nb::Error<Rui3Error>> {
+        self.start_rx(receiving_window)
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
-    #[allow(clippy::missing_panics_doc)]
-    /// URC polling function, returns data as a vec u8.
+    /// URC polling function, returns the received packet.
+    ///
+    /// Returns [`nb::Error::WouldBlock`] if no packet has been received yet.
     ///
     /// This function is usually only called through the [`receive`] function.
     ///
     /// [`receive`]: #method.receive
-    pub fn poll(&mut self) -> Result<alloc::vec::Vec<u8>, nb::Error<atat::Error>> {
-        // Check for URCs in loop.
-        let check_urc = self.client.check_urc::<at::urc::URCMessages>();
-
-        match check_urc {
-            Some(at::urc::URCMessages::PeerToPeerData(hex_data)) => {
-                // let data = decode(hex_data).unwrap();
-
-                // if hex_data.len() % 2 != 0 {
-                //     Err
-                // }
-
-                let hex_data = alloc::string::String::from_utf8(hex_data).unwrap();
-                let mut data = alloc::vec::Vec::new();
-                for i in (0..hex_data.len()).step_by(2) {
-                    let byte = u8::from_str_radix(&hex_data[i..i + 2], 16).unwrap();
-                    data.push(byte);
-                }
+    pub fn poll<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        clock: &mut CLK,
+    ) -> Result<ReceivedPacket<TIMER_HZ>, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        self.check_packet(clock).ok_or(nb::Error::WouldBlock)
+    }
 
-                Ok(data)
-            }
-            Some(at::urc::URCMessages::PeerToPeerInfo { rssi, snr }) => {
-                self.rssi = rssi;
-                self.snr = snr;
-                Ok(alloc::vec![])
+    #[allow(missing_doc_code_examples)]
+    /// URC polling function, copies the received payload into a caller-provided
+    /// buffer.
+    ///
+    /// Returns the number of bytes written to `buf` with the metadata of the packet,
+    /// or [`nb::Error::WouldBlock`] if no packet has been received yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::BufferTooSmall`] if the payload does not fit in `buf`.
+    pub fn poll_into<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        buf: &mut [u8],
+        clock: &mut CLK,
+    ) -> Result<PacketInfo<TIMER_HZ>, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        let packet = self.check_packet(clock).ok_or(nb::Error::WouldBlock)?;
+        copy_packet(&packet, buf)
+    }
+
+    /// Sets client to the desired configuration.
+    ///
+    /// Only sends the parameters which differ from the configuration of the module.
+    /// The configuration is read from the module on the first call, then mirrored
+    /// from what was applied. An empty encryption key is never sent.
+    ///
+    /// Changing the working mode restarts the module, the other parameters are set
+    /// once it answers again.
+    ///
+    /// # Arguments
+    ///
+    /// * `configuration` - The configuration to apply.
+    /// * `clock` - The clock used to wait for the module after a restart.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// let changes = radio_client.configure(configuration, &mut clock)?;
+    /// log::info!("Changed {changes:?}");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Rui3Error`] if the module rejects a command or the AT client fails,
+    /// or [`Rui3Error::NotResponding`] if it does not answer within 5 seconds after a
+    /// restart. The parameters set before the error are kept, and the configuration
+    /// is read again on the next call.
+    pub fn configure<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        configuration: Configuration,
+        clock: &mut CLK,
+    ) -> Result<ConfigurationChanges, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        log::debug!("Config starting: {configuration:?}");
+        // Kept to apply it again after a restart.
+        let configuration = self.requested_configuration.insert(configuration).clone();
+        if !self.configuration_known {
+            self.read_configuration()?;
+        }
+        let differing = configuration.diff(&self.configuration);
+        let mut plan = ConfigurationPlan::new(&configuration, differing, self.combined_p2p);
+        let result = self.apply_plan(&mut plan, &configuration, clock);
+        self.combined_p2p = plan.combined_p2p();
+
+        match result {
+            Ok(()) => {
+                let changes = plan.into_changes();
+                log::debug!("Changed {changes:?}");
+                Ok(changes)
             }
-            Some(at::urc::URCMessages::PeerToPeerMessage { rssi, snr, data }) => {
-                self.rssi = rssi;
-                self.snr = snr;
-                Ok(data)
+            Err(error) => {
+                // Some parameters may be set, the module must be read again.
+                self.configuration_known = false;
+                Err(error)
             }
-            None => Ok(alloc::vec![]),
         }
     }
 
+    /// Reads the configuration of the module and returns the parameters the
+    /// profile would change.
+    ///
+    /// # Arguments
+    ///
+    /// * `profile` - The profile to compare against the module.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// let track = profiles.get("track").unwrap();
+    /// if !radio_client.diff_profile(track)?.is_empty() {
+    ///     radio_client.apply_profile(track, &mut clock)?;
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Rui3Error`] if the configuration cannot be read.
+    pub fn diff_profile(
+        &mut self,
+        profile: &Profile,
+    ) -> Result<ConfigurationChanges, nb::Error<Rui3Error>> {
+        let current = self.read_configuration()?;
+        Ok(profile.configuration.diff(&current))
+    }
+
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
-    /// Sets client to the desired configuration.
+    /// Applies the configuration of a profile, like [`configure`].
     ///
-    /// Takes as parameter a `Configuration` struct and returns nothing.
-    pub fn configure(
+    /// [`configure`]: #method.configure
+    pub fn apply_profile<CLK, const TIMER_HZ: u32>(
         &mut self,
-        configuration: Configuration,
-    ) -> Result<(), nb::Error<atat::Error>> {
-        // // Set the working mode.
-        // println!(
-        //     "Trying to set the working mode to: {:?}",
-        //     configuration.working_mode
-        // );
-        // self.client
-        //     .send(&at::commands::p2p::SetNetworkWorkingMode {
-        //         mode: configuration.working_mode,
-        //     })?;
-
-        // Set the frequency.
-        println!("Config starting");
-        println!(
-            "Trying to set the frequency to: {}",
-            configuration.frequency
-        );
-        self.client.send(&at::commands::p2p::SetP2PFrequency {
-            frequency: configuration.frequency,
-        })?;
-
-        // Set the spreading factor.
-        println!(
-            "Trying to set the spreading factor to: {:?}",
-            configuration.spreading_factor
-        );
-        self.client
-            .send(&at::commands::p2p::SetP2PSpreadingFactor {
-                spreading_factor: configuration.spreading_factor,
-            })?;
-        // Set the bandwidth.
-        println!(
-            "Trying to set the bandwidth to: {:?}",
-            configuration.bandwidth
-        );
-        self.client.send(&at::commands::p2p::SetP2PBandwidth {
-            bandwidth: configuration.bandwidth,
-        })?;
-        // Set the code rate.
-        println!(
-            "Trying to set the code rate to: {:?}",
-            configuration.code_rate
-        );
-        self.client.send(&at::commands::p2p::SetCodeRate {
-            code_rate: configuration.code_rate,
-        })?;
-        // Set the preamble length.
-        println!(
-            "Trying to set the preamble length to: {:?}",
-            configuration.preamble_length
-        );
-        self.client.send(&at::commands::p2p::SetPreambleLength {
-            preamble_length: configuration.preamble_length,
-        })?;
-        // Set the TX power.
-        println!(
-            "Trying to set the TX power to: {:?}",
-            configuration.tx_power
-        );
-        self.client.send(&at::commands::p2p::SetTxPower {
-            tx_power: configuration.tx_power,
-        })?;
-        // // Set the encryption mode.
-        // println!(
-        //     "Trying to set the encryption mode to: {:?}",
-        //     configuration.encrypted
-        // );
-        // self.client.send(&at::commands::p2p::SetEncryptionMode {
-        //     encryption: configuration.encrypted,
-        // })?;
-        // // Set the encryption key.
-        // println!(
-        //     "Trying to set the encryption key to: {:?}",
-        //     configuration.encryption_key
-        // );
-        // self.client.send(&at::commands::p2p::SetEncryptionKey {
-        //     encryption_key: configuration.encryption_key,
-        // })?;
-
-        Ok(())
-    }
+        profile: &Profile,
+        clock: &mut CLK,
+    ) -> Result<ConfigurationChanges, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        log::debug!("Applying profile {}", profile.name);
+        self.configure(profile.configuration.clone(), clock)
+    }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Reads client configuration and returns a `Configuration` struct.
-    pub fn read_configuration(&mut self) -> Result<Configuration, nb::Error<atat::Error>> {
+    pub fn read_configuration(&mut self) -> Result<Configuration, nb::Error<Rui3Error>> {
         // Get the network working mode.
-        let working_mode = self
-            .client
-            .send(&at::commands::p2p::GetNetworkWorkingMode {})?;
+        let working_mode = self.send_command(&at::commands::p2p::GetNetworkWorkingMode {})?;
 
-        // Get the frequency.
-        let frequency = self.client.send(&at::commands::p2p::GetP2PFrequency {})?;
-        // Get the spreading factor.
-        let spreading_factor = self
-            .client
-            .send(&at::commands::p2p::GetP2PSpreadingFactor {})?;
-        // Get the bandwidth.
-        let bandwidth = self.client.send(&at::commands::p2p::GetP2PBandwidth {})?;
-        // Get the code rate.
-        let code_rate = self.client.send(&at::commands::p2p::GetCodeRate {})?;
-        // Get the preamble length.
-        let preamble_length = self.client.send(&at::commands::p2p::GetPreambleLength {})?;
-        // Get the TX power.
-        let tx_power = self.client.send(&at::commands::p2p::GetTxPower {})?;
+        // Get the modulation parameters.
+        let fsk = working_mode.mode == at::commands::p2p::WorkingMode::FskP2P;
+        let parameters = if fsk {
+            self.read_fsk_parameters()?
+        } else {
+            self.read_p2p_parameters()?
+        };
+        // The FSK parameters only apply in FSK mode, otherwise keep the known ones.
+        let (fsk_bitrate, fsk_deviation) = if fsk {
+            let bitrate = self.send_command(&at::commands::p2p::GetFskBitrate {})?;
+            let deviation = self.send_command(&at::commands::p2p::GetFskDeviation {})?;
+            (bitrate.bitrate, deviation.deviation)
+        } else {
+            (
+                self.configuration.fsk_bitrate,
+                self.configuration.fsk_deviation,
+            )
+        };
         // Get the encryption key.
-        let encryption_key = self.client.send(&at::commands::p2p::GetEncryptionKey {})?;
+        let encryption_key = self.send_command(&at::commands::p2p::GetEncryptionKey {})?;
         // Get the encryption mode.
-        let encryption_mode = self.client.send(&at::commands::p2p::GetEncryptionMode {})?;
+        let encryption_mode = self.send_command(&at::commands::p2p::GetEncryptionMode {})?;
+        // Get the sync word.
+        let sync_word = self.send_command(&at::commands::p2p::GetSyncWord {})?;
+        // Get the IQ inversion.
+        let iq_inversion = self.send_command(&at::commands::p2p::GetIqInversion {})?;
+        // Get the symbol timeout.
+        let symbol_timeout = self.send_command(&at::commands::p2p::GetSymbolTimeout {})?;
 
         let configuration = Configuration {
             working_mode: working_mode.mode,
-            frequency: frequency.frequency,
-            spreading_factor: spreading_factor.spreading_factor,
-            bandwidth: bandwidth.bandwidth,
-            code_rate: code_rate.code_rate,
-            preamble_length: preamble_length.preamble_length,
-            tx_power: tx_power.tx_power,
+            frequency: parameters.frequency,
+            spreading_factor: parameters.spreading_factor,
+            bandwidth: parameters.bandwidth,
+            code_rate: parameters.coderate,
+            preamble_length: parameters.preamblelength,
+            tx_power: parameters.txpower,
             encrypted: encryption_mode.encryption,
             encryption_key: encryption_key.encryption_key,
+            fsk_bitrate,
+            fsk_deviation,
+            sync_word: u16::from_str_radix(&sync_word.sync_word, 16)
+                .map_err(|_| nb::Error::Other(Rui3Error::Atat(atat::Error::Parse)))?,
+            iq_inversion: iq_inversion.iq_inversion.into(),
+            symbol_timeout: symbol_timeout.symbol_timeout,
         };
 
+        self.configuration = configuration.clone();
+        self.configuration_known = true;
+
         // Return configuration
         Ok(configuration)
     }
 
-    /// Returns the 'Received signal strength indicator' (RSSI) value.
-    pub const fn get_rssi(&self) -> i16 {
-        self.rssi
+    /// Returns what the module is doing.
+    ///
+    /// Tracked from the commands sent and the events received, and assumed
+    /// [`RadioState::Idle`] until the first one.
+    #[must_use]
+    pub const fn state(&self) -> RadioState {
+        self.state
+    }
+
+    /// Returns the signal quality of the last received packet.
+    pub fn link_quality(&self) -> Option<LinkQuality> {
+        self.link_quality.recent().copied()
     }
 
-    /// Returns the 'Signal to noise ratio' (SNR) value.
-    pub const fn get_snr(&self) -> i16 {
-        self.snr
+    /// Returns the signal quality of the last received packets, oldest first.
+    ///
+    /// Up to [`LINK_QUALITY_HISTORY_LEN`] packets are kept.
+    pub fn link_quality_history(&self) -> impl Iterator<Item = &LinkQuality> {
+        self.link_quality.oldest_ordered()
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
-    /// Sets the network working mode.
-    pub fn set_network_working_mode(
+    /// Sets the network working mode, then waits for the module to restart.
+    pub fn set_network_working_mode<CLK, const TIMER_HZ: u32>(
         &mut self,
         working_mode: at::commands::p2p::WorkingMode,
-    ) -> Result<(), nb::Error<atat::Error>> {
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
         // Set the working mode.
-        self.client
-            .send(&at::commands::p2p::SetNetworkWorkingMode { mode: working_mode })?;
-        Ok(())
+        self.send_command(&at::commands::p2p::SetNetworkWorkingMode {
+            mode: working_mode.clone(),
+        })?;
+        self.configuration.working_mode = working_mode;
+        self.wait_for_restart(clock)
     }
 
     #[allow(missing_doc_code_examples)]
@@ -543,30 +1108,28 @@ where
     /// Gets the network working mode.
     pub fn get_network_working_mode(
         &mut self,
-    ) -> Result<at::commands::p2p::WorkingMode, nb::Error<atat::Error>> {
+    ) -> Result<at::commands::p2p::WorkingMode, nb::Error<Rui3Error>> {
         // Get the network working mode.
-        let working_mode = self
-            .client
-            .send(&at::commands::p2p::GetNetworkWorkingMode {})?;
+        let working_mode = self.send_command(&at::commands::p2p::GetNetworkWorkingMode {})?;
         Ok(working_mode.mode)
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Sets the frequency.
-    pub fn set_frequency(&mut self, frequency: u32) -> Result<(), nb::Error<atat::Error>> {
+    pub fn set_frequency(&mut self, frequency: u32) -> Result<(), nb::Error<Rui3Error>> {
         // Set the frequency.
-        self.client
-            .send(&at::commands::p2p::SetP2PFrequency { frequency })?;
+        self.send_command(&at::commands::p2p::SetP2PFrequency { frequency })?;
+        self.configuration.frequency = frequency;
         Ok(())
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Gets the frequency.
-    pub fn get_frequency(&mut self) -> Result<u32, nb::Error<atat::Error>> {
+    pub fn get_frequency(&mut self) -> Result<u32, nb::Error<Rui3Error>> {
         // Get the frequency.
-        let frequency = self.client.send(&at::commands::p2p::GetP2PFrequency {})?;
+        let frequency = self.send_command(&at::commands::p2p::GetP2PFrequency {})?;
         Ok(frequency.frequency)
     }
 
@@ -576,21 +1139,19 @@ where
     pub fn set_spreading_factor(
         &mut self,
         spreading_factor: u8,
-    ) -> Result<(), nb::Error<atat::Error>> {
+    ) -> Result<(), nb::Error<Rui3Error>> {
         // Set the spreading factor.
-        self.client
-            .send(&at::commands::p2p::SetP2PSpreadingFactor { spreading_factor })?;
+        self.send_command(&at::commands::p2p::SetP2PSpreadingFactor { spreading_factor })?;
+        self.configuration.spreading_factor = spreading_factor;
         Ok(())
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Gets the spreading factor.
-    pub fn get_spreading_factor(&mut self) -> Result<u8, nb::Error<atat::Error>> {
+    pub fn get_spreading_factor(&mut self) -> Result<u8, nb::Error<Rui3Error>> {
         // Get the spreading factor.
-        let spreading_factor = self
-            .client
-            .send(&at::commands::p2p::GetP2PSpreadingFactor {})?;
+        let spreading_factor = self.send_command(&at::commands::p2p::GetP2PSpreadingFactor {})?;
         Ok(spreading_factor.spreading_factor)
     }
 
@@ -600,21 +1161,21 @@ where
     pub fn set_bandwidth(
         &mut self,
         bandwidth: at::commands::p2p::Bandwidth,
-    ) -> Result<(), nb::Error<atat::Error>> {
+    ) -> Result<(), nb::Error<Rui3Error>> {
         // Set the bandwidth.
-        self.client
-            .send(&at::commands::p2p::SetP2PBandwidth { bandwidth })?;
+        self.send_command(&at::commands::p2p::SetP2PBandwidth {
+            bandwidth: bandwidth.clone(),
+        })?;
+        self.configuration.bandwidth = bandwidth;
         Ok(())
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Gets the bandwidth.
-    pub fn get_bandwidth(
-        &mut self,
-    ) -> Result<at::commands::p2p::Bandwidth, nb::Error<atat::Error>> {
+    pub fn get_bandwidth(&mut self) -> Result<at::commands::p2p::Bandwidth, nb::Error<Rui3Error>> {
         // Get the bandwidth.
-        let bandwidth = self.client.send(&at::commands::p2p::GetP2PBandwidth {})?;
+        let bandwidth = self.send_command(&at::commands::p2p::GetP2PBandwidth {})?;
         Ok(bandwidth.bandwidth)
     }
 
@@ -624,19 +1185,21 @@ where
     pub fn set_code_rate(
         &mut self,
         code_rate: at::commands::p2p::CodeRate,
-    ) -> Result<(), nb::Error<atat::Error>> {
+    ) -> Result<(), nb::Error<Rui3Error>> {
         // Set the code rate.
-        self.client
-            .send(&at::commands::p2p::SetCodeRate { code_rate })?;
+        self.send_command(&at::commands::p2p::SetCodeRate {
+            code_rate: code_rate.clone(),
+        })?;
+        self.configuration.code_rate = code_rate;
         Ok(())
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Gets the code rate.
-    pub fn get_code_rate(&mut self) -> Result<at::commands::p2p::CodeRate, nb::Error<atat::Error>> {
+    pub fn get_code_rate(&mut self) -> Result<at::commands::p2p::CodeRate, nb::Error<Rui3Error>> {
         // Get the code rate.
-        let code_rate = self.client.send(&at::commands::p2p::GetCodeRate {})?;
+        let code_rate = self.send_command(&at::commands::p2p::GetCodeRate {})?;
         Ok(code_rate.code_rate)
     }
 
@@ -646,57 +1209,62 @@ where
     pub fn set_preamble_length(
         &mut self,
         preamble_length: u16,
-    ) -> Result<(), nb::Error<atat::Error>> {
+    ) -> Result<(), nb::Error<Rui3Error>> {
         // Set the preamble length.
-        self.client
-            .send(&at::commands::p2p::SetPreambleLength { preamble_length })?;
+        self.send_command(&at::commands::p2p::SetPreambleLength { preamble_length })?;
+        self.configuration.preamble_length = preamble_length;
         Ok(())
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Gets the preamble length.
-    pub fn get_preamble_length(&mut self) -> Result<u16, nb::Error<atat::Error>> {
+    pub fn get_preamble_length(&mut self) -> Result<u16, nb::Error<Rui3Error>> {
         // Get the preamble length.
-        let preamble_length = self.client.send(&at::commands::p2p::GetPreambleLength {})?;
+        let preamble_length = self.send_command(&at::commands::p2p::GetPreambleLength {})?;
         Ok(preamble_length.preamble_length)
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Sets tx power.
-    pub fn set_tx_power(&mut self, tx_power: u8) -> Result<(), nb::Error<atat::Error>> {
+    pub fn set_tx_power(&mut self, tx_power: u8) -> Result<(), nb::Error<Rui3Error>> {
         // Set tx power.
-        self.client
-            .send(&at::commands::p2p::SetTxPower { tx_power })?;
+        self.send_command(&at::commands::p2p::SetTxPower { tx_power })?;
+        self.configuration.tx_power = tx_power;
         Ok(())
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Gets tx power.
-    pub fn get_tx_power(&mut self) -> Result<u8, nb::Error<atat::Error>> {
+    pub fn get_tx_power(&mut self) -> Result<u8, nb::Error<Rui3Error>> {
         // Get tx power.
-        let tx_power = self.client.send(&at::commands::p2p::GetTxPower {})?;
+        let tx_power = self.send_command(&at::commands::p2p::GetTxPower {})?;
         Ok(tx_power.tx_power)
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Sets the encryption mode.
-    pub fn set_encryption_mode(&mut self, encryption: Encrypted) -> Result<(), nb::Error<atat::Error>> {
+    pub fn set_encryption_mode(
+        &mut self,
+        encryption: Encrypted,
+    ) -> Result<(), nb::Error<Rui3Error>> {
         // Set the encryption mode.
-        self.client
-            .send(&at::commands::p2p::SetEncryptionMode { encryption })?;
+        self.send_command(&at::commands::p2p::SetEncryptionMode {
+            encryption: encryption.clone(),
+        })?;
+        self.configuration.encrypted = encryption;
         Ok(())
     }
 
     #[allow(missing_doc_code_examples)]
     #[allow(clippy::missing_errors_doc)]
     /// Gets the encryption mode.
-    pub fn get_encryption_mode(&mut self) -> Result<Encrypted, nb::Error<atat::Error>> {
+    pub fn get_encryption_mode(&mut self) -> Result<Encrypted, nb::Error<Rui3Error>> {
         // Get the encryption mode.
-        let encryption = self.client.send(&at::commands::p2p::GetEncryptionMode {})?;
+        let encryption = self.send_command(&at::commands::p2p::GetEncryptionMode {})?;
         Ok(encryption.encryption)
     }
 
@@ -706,10 +1274,12 @@ where
     pub fn set_encryption_key(
         &mut self,
         encryption_key: atat::heapless::String<16>,
-    ) -> Result<(), nb::Error<atat::Error>> {
+    ) -> Result<(), nb::Error<Rui3Error>> {
         // Set the encryption key.
-        self.client
-            .send(&at::commands::p2p::SetEncryptionKey { encryption_key })?;
+        self.send_command(&at::commands::p2p::SetEncryptionKey {
+            encryption_key: encryption_key.clone(),
+        })?;
+        self.configuration.encryption_key = encryption_key;
         Ok(())
     }
 
@@ -718,9 +1288,1296 @@ where
     /// Gets the encryption key.
     pub fn get_encryption_key(
         &mut self,
-    ) -> Result<atat::heapless::String<16>, nb::Error<atat::Error>> {
+    ) -> Result<atat::heapless::String<16>, nb::Error<Rui3Error>> {
         // Get the encryption key.
-        let encryption_key = self.client.send(&at::commands::p2p::GetEncryptionKey {})?;
+        let encryption_key = self.send_command(&at::commands::p2p::GetEncryptionKey {})?;
         Ok(encryption_key.encryption_key)
     }
+
+    #[allow(missing_doc_code_examples)]
+    #[allow(clippy::missing_errors_doc)]
+    /// Sets whether the module checks the channel for activity before transmitting.
+    ///
+    /// When the channel is busy, the module does not transmit and the send functions
+    /// return [`Rui3Error::ChannelBusy`].
+    pub fn set_channel_activity_detection(
+        &mut self,
+        enabled: bool,
+    ) -> Result<(), nb::Error<Rui3Error>> {
+        self.send_command(&at::commands::p2p::SetChannelActivityDetection {
+            detection: enabled.into(),
+        })?;
+        self.channel_activity_detection = enabled;
+        Ok(())
+    }
+
+    #[allow(missing_doc_code_examples)]
+    #[allow(clippy::missing_errors_doc)]
+    /// Gets whether the module checks the channel for activity before transmitting.
+    pub fn get_channel_activity_detection(&mut self) -> Result<bool, nb::Error<Rui3Error>> {
+        let cad = self.send_command(&at::commands::p2p::GetChannelActivityDetection {})?;
+        self.channel_activity_detection = cad.detection.into();
+        Ok(self.channel_activity_detection)
+    }
+
+    /// Reads the serial number, firmware version, hardware model and alias of the
+    /// module.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// let info = radio_client.device_info()?;
+    /// if info.firmware_version < FirmwareVersion::parse("4.0.5") {
+    ///     log::warn!("{} runs an old firmware: {}", info.serial_number, info.firmware);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub fn device_info(&mut self) -> Result<DeviceInfo, nb::Error<Rui3Error>> {
+        let serial_number = self
+            .send_command(&at::commands::general::SerialNumber {})?
+            .serial_number;
+        let firmware = self
+            .send_command(&at::commands::general::FirmwareVersion {})?
+            .firmware_version;
+        let hardware_model = self
+            .send_command(&at::commands::general::HardwareModel {})?
+            .hardware_version;
+        let alias = self
+            .send_command(&at::commands::general::GetAlias {})?
+            .alias;
+
+        Ok(DeviceInfo {
+            serial_number,
+            firmware_version: FirmwareVersion::parse(&firmware),
+            firmware,
+            model: Model::from_hardware_model(&hardware_model),
+            hardware_model,
+            alias,
+        })
+    }
+
+    #[allow(missing_doc_code_examples)]
+    /// Sets the alias of the module, up to 16 characters.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::ParamError`] if `alias` is too long, or another
+    /// [`Rui3Error`] if the module rejects the command or the AT client fails.
+    pub fn set_alias(&mut self, alias: &str) -> Result<(), nb::Error<Rui3Error>> {
+        let mut value = atat::heapless::String::new();
+        value
+            .push_str(alias)
+            .map_err(|()| nb::Error::Other(Rui3Error::ParamError))?;
+        self.send_command(&at::commands::general::SetAlias { alias: value })?;
+        Ok(())
+    }
+
+    /// Restarts the module, e.g. when it no longer answers as expected.
+    ///
+    /// Sends `ATZ`, then `AT` with a growing delay until the module answers, and
+    /// finally applies again the last configuration passed to [`configure`], as well
+    /// as channel activity detection if it was enabled. RX is left disabled.
+    ///
+    /// [`configure`]: #method.configure
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The clock used to wait for the module.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// if let Err(nb::Error::Other(Rui3Error::TxTimeout)) = radio_client.send(&data, &mut clock) {
+    ///     radio_client.reset(&mut clock)?;
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::NotResponding`] if the module does not answer within 5
+    /// seconds, or another [`Rui3Error`] if the configuration cannot be applied.
+    pub fn reset<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        // The module may restart before answering.
+        if let Err(error) = self.send_command(&at::commands::general::McuReset {}) {
+            log::debug!("No answer to ATZ: {error:?}");
+        }
+        self.recover(clock)
+    }
+
+    /// Restores the default parameters of the module, then applies the last
+    /// configuration again.
+    ///
+    /// Same as [`reset`] with `ATR`, which also clears the parameters not covered by
+    /// [`Configuration`], such as the `LoRaWAN` keys and the alias.
+    ///
+    /// [`reset`]: #method.reset
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - The clock used to wait for the module.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// radio_client.factory_reset(&mut clock)?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::NotResponding`] if the module does not answer within 5
+    /// seconds, or another [`Rui3Error`] if the configuration cannot be applied.
+    pub fn factory_reset<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        // The module may restart before answering.
+        if let Err(error) = self.send_command(&at::commands::general::RestoreDefaultParameters {}) {
+            log::debug!("No answer to ATR: {error:?}");
+        }
+        self.recover(clock)
+    }
+
+    /// Disables RX and sends data, without waiting for the end of the transmission.
+    fn start_transmission(&mut self, data: &[u8]) -> Result<(), nb::Error<Rui3Error>> {
+        // Convert each byte of data to a hex string.
+        let mut payload: atat::heapless::String<500> =
+            encode_hex(data).ok_or(nb::Error::Other(Rui3Error::ParamError))?;
+        // Add \r\n to the end of the string.
+        payload
+            .push_str("\r\n")
+            .map_err(|()| nb::Error::Other(Rui3Error::ParamError))?;
+
+        // Disable RX.
+        self.start_rx(at::commands::p2p::ReceiveWindow::StopListening)?;
+
+        // Send data.
+        self.send_command_retry(&at::commands::p2p::SendData { payload })?;
+        self.state = RadioState::Tx;
+        log::debug!("Sending data: {data:?}");
+        Ok(())
+    }
+
+    /// Re-enables RX once a transmission is done.
+    fn finish_transmission(&mut self) -> Result<(), nb::Error<Rui3Error>> {
+        self.send_command_retry(&at::commands::p2p::ReceiveData {
+            window: at::commands::p2p::ReceiveWindow::Continuous,
+        })?;
+        self.state = RadioState::Rx;
+        Ok(())
+    }
+
+    /// Stops waiting for the end of a transmission, and returns
+    /// [`Rui3Error::TxTimeout`].
+    fn abandon_transmission(&mut self) -> nb::Error<Rui3Error> {
+        log::debug!("Transmission timed out");
+        // Let the module reject the next commands if it is still transmitting.
+        self.state = RadioState::Idle;
+        nb::Error::Other(Rui3Error::TxTimeout)
+    }
+
+    /// Tracks the end of an RX window whose end was not reported.
+    fn close_rx_window(&mut self) {
+        if self.state == RadioState::RxWindow {
+            self.state = RadioState::Idle;
+        }
+    }
+
+    /// Checks whether the module reported the end of a transmission.
+    ///
+    /// If the module found the channel busy instead, re-enables RX and returns
+    /// [`Rui3Error::ChannelBusy`].
+    fn check_tx_done(&mut self) -> Result<bool, nb::Error<Rui3Error>> {
+        match self.check_event() {
+            Some(at::urc::URCMessages::PeerToPeerTxDone) => Ok(true),
+            Some(at::urc::URCMessages::CadDetected) => {
+                log::debug!("Channel busy, nothing sent");
+                self.finish_transmission()?;
+                Err(nb::Error::Other(Rui3Error::ChannelBusy))
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Waits for the module to answer after a restart, then applies the last
+    /// configuration again.
+    fn recover<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        self.wait_for_restart(clock)?;
+
+        if let Some(configuration) = self.requested_configuration.clone() {
+            // The module may have lost parameters, it must be read again.
+            self.configuration_known = false;
+            self.configure(configuration, clock)?;
+        }
+        if self.channel_activity_detection {
+            self.set_channel_activity_detection(true)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the module to answer after a restart, and forgets what it was doing.
+    fn wait_for_restart<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        // The module boots neither receiving nor transmitting.
+        self.state = RadioState::Idle;
+        self.wait_for_boot(clock)?;
+        // Drop the boot banner and the events from before the restart.
+        self.client.reset();
+        Ok(())
+    }
+
+    /// Sends `AT` until the module answers, waiting longer after each attempt.
+    fn wait_for_boot<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        let mut delays = boot_poll_delays();
+        loop {
+            match self.send_command(&at::commands::general::Attention {}) {
+                Ok(_) => return Ok(()),
+                Err(error) => log::debug!("Module not ready: {error:?}"),
+            }
+
+            let Some(delay) = delays.next() else {
+                return Err(nb::Error::Other(Rui3Error::NotResponding));
+            };
+            wait(clock, delay);
+        }
+    }
+
+    /// Draws a listen-before-talk backoff between half of `backoff` and `backoff`.
+    fn next_backoff(
+        &mut self,
+        backoff: fugit::MillisDurationU32,
+        ticks: u32,
+    ) -> fugit::MillisDurationU32 {
+        let mut x = (self.backoff_seed ^ ticks).max(1);
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.backoff_seed = x;
+
+        let max = backoff.ticks();
+        let min = max / 2;
+        fugit::MillisDurationU32::millis(min + x % (max - min + 1))
+    }
+
+    /// Stops RX, then enables it again in continuous mode.
+    ///
+    /// Sends `AT+PRECV=0` even if the tracked state is idle, as the module may still
+    /// be receiving after a failed command. Like [`Self::start_rx`], returns
+    /// [`Rui3Error::Transmitting`] while a transmission is in progress.
+    fn restart_continuous_rx(&mut self) -> Result<(), nb::Error<Rui3Error>> {
+        self.start_rx(at::commands::p2p::ReceiveWindow::StopListening)?;
+
+        // Enable RX.
+        self.start_rx(at::commands::p2p::ReceiveWindow::Continuous)
+    }
+
+    /// Sends `AT+PRECV` and tracks the resulting state.
+    ///
+    /// Stops RX first if the module is receiving, as it rejects new windows until
+    /// then.
+    fn start_rx(
+        &mut self,
+        window: at::commands::p2p::ReceiveWindow,
+    ) -> Result<(), nb::Error<Rui3Error>> {
+        if self.state == RadioState::Tx {
+            return Err(nb::Error::Other(Rui3Error::Transmitting));
+        }
+        let state = match window {
+            at::commands::p2p::ReceiveWindow::StopListening => RadioState::Idle,
+            at::commands::p2p::ReceiveWindow::Continuous => RadioState::Rx,
+            at::commands::p2p::ReceiveWindow::Milliseconds(_) => RadioState::RxWindow,
+            at::commands::p2p::ReceiveWindow::OnePacket => RadioState::RxOnePacket,
+        };
+        if state != RadioState::Idle && self.state != RadioState::Idle {
+            self.start_rx(at::commands::p2p::ReceiveWindow::StopListening)?;
+        }
+        self.send_command(&at::commands::p2p::ReceiveData { window })?;
+        self.state = state;
+        Ok(())
+    }
+
+    /// Checks for a URC, tracking the end of transmissions and RX windows.
+    fn check_urc(&mut self) -> Option<at::urc::URCMessages> {
+        let urc = self.client.check_urc::<at::urc::URCMessages>()?;
+        self.state = match (&urc, self.state) {
+            (
+                at::urc::URCMessages::PeerToPeerTxDone | at::urc::URCMessages::CadDetected,
+                RadioState::Tx,
+            )
+            | (
+                at::urc::URCMessages::PeerToPeerReceiveTimeout,
+                RadioState::RxWindow | RadioState::RxOnePacket,
+            )
+            | (at::urc::URCMessages::PeerToPeerMessage { .. }, RadioState::RxOnePacket) => {
+                RadioState::Idle
+            }
+            (_, state) => state,
+        };
+        Some(urc)
+    }
+
+    /// Returns the next URC, starting with the packets kept by [`Self::check_event`].
+    fn next_urc(&mut self) -> Option<at::urc::URCMessages> {
+        if let Some((rssi, snr, data)) = self.kept_packets.pop_front() {
+            return Some(at::urc::URCMessages::PeerToPeerMessage { rssi, snr, data });
+        }
+        self.check_urc()
+    }
+
+    /// Checks for a URC other than a received packet.
+    ///
+    /// Received packets are kept for the next receive, up to [`KEPT_PACKETS_LEN`].
+    fn check_event(&mut self) -> Option<at::urc::URCMessages> {
+        match self.check_urc()? {
+            at::urc::URCMessages::PeerToPeerMessage { rssi, snr, data } => {
+                if self.kept_packets.is_full() {
+                    log::warn!("Too many packets received meanwhile, dropping the oldest");
+                    self.kept_packets.pop_front();
+                }
+                // There is room after dropping the oldest packet.
+                let _ = self.kept_packets.push_back((rssi, snr, data));
+                None
+            }
+            urc => Some(urc),
+        }
+    }
+
+    /// Receives packets with the given receiving window, handing each one to
+    /// `on_packet`.
+    fn receive_explicit_packets<CLK, F, const TIMER_HZ: u32>(
+        &mut self,
+        receiving_window: &at::commands::p2p::ReceiveWindow,
+        clock: &mut CLK,
+        mut on_packet: F,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+        F: FnMut(ReceivedPacket<TIMER_HZ>),
+    {
+        match receiving_window {
+            at::commands::p2p::ReceiveWindow::Milliseconds(millis) => {
+                // Enable RX
+                self.start_rx(at::commands::p2p::ReceiveWindow::Milliseconds(*millis))?;
+
+                let window = fugit::MillisDurationU32::millis(u32::from(*millis));
+                let deadline = clock.now() + window.convert();
+                // Collect packets until the module closes the window or it elapses.
+                while clock.now() < deadline {
+                    match self.next_urc() {
+                        Some(at::urc::URCMessages::PeerToPeerMessage { rssi, snr, data }) => {
+                            on_packet(self.record_packet(rssi, snr, data, clock));
+                        }
+                        Some(at::urc::URCMessages::PeerToPeerReceiveTimeout) => break,
+                        _ => {}
+                    }
+                }
+                // The module closed the window, even if the event was missed.
+                self.close_rx_window();
+            }
+            at::commands::p2p::ReceiveWindow::OnePacket => {
+                // Enable RX
+                self.start_rx(at::commands::p2p::ReceiveWindow::OnePacket)?;
+
+                on_packet(self.wait_for_packet(clock));
+            }
+            at::commands::p2p::ReceiveWindow::Continuous => {
+                self.restart_continuous_rx()?;
+                on_packet(self.wait_for_packet(clock));
+            }
+            at::commands::p2p::ReceiveWindow::StopListening => {
+                // Disable RX
+                self.start_rx(at::commands::p2p::ReceiveWindow::StopListening)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks for URCs in a loop until a packet is received.
+    fn wait_for_packet<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        clock: &mut CLK,
+    ) -> ReceivedPacket<TIMER_HZ>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        loop {
+            if let Some(packet) = self.check_packet(clock) {
+                return packet;
+            }
+        }
+    }
+
+    /// Checks for a received packet.
+    fn check_packet<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        clock: &mut CLK,
+    ) -> Option<ReceivedPacket<TIMER_HZ>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        match self.next_urc()? {
+            at::urc::URCMessages::PeerToPeerMessage { rssi, snr, data } => {
+                Some(self.record_packet(rssi, snr, data, clock))
+            }
+            _ => None,
+        }
+    }
+
+    /// Timestamps a received packet and adds its signal quality to the history.
+    fn record_packet<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        rssi: i16,
+        snr: i16,
+        payload: at::urc::Payload,
+        clock: &mut CLK,
+    ) -> ReceivedPacket<TIMER_HZ>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        self.link_quality.write(LinkQuality { rssi, snr });
+        ReceivedPacket {
+            payload,
+            rssi,
+            snr,
+            timestamp: clock.now(),
+        }
+    }
+
+    /// Reads the modulation parameters, at once with `AT+P2P` if the firmware knows it.
+    fn read_p2p_parameters(
+        &mut self,
+    ) -> Result<at::responses::p2p::P2Pparameters, nb::Error<Rui3Error>> {
+        if self.combined_p2p {
+            match self.send_command(&at::commands::p2p::GetP2P {}) {
+                Err(nb::Error::Other(Rui3Error::CommandNotFound)) => {
+                    log::debug!("AT+P2P not supported, reading parameters one by one");
+                    self.combined_p2p = false;
+                }
+                result => return result,
+            }
+        }
+
+        // Get the frequency.
+        let frequency = self.send_command(&at::commands::p2p::GetP2PFrequency {})?;
+        // Get the spreading factor.
+        let spreading_factor = self.send_command(&at::commands::p2p::GetP2PSpreadingFactor {})?;
+        // Get the bandwidth.
+        let bandwidth = self.send_command(&at::commands::p2p::GetP2PBandwidth {})?;
+        // Get the code rate.
+        let code_rate = self.send_command(&at::commands::p2p::GetCodeRate {})?;
+        // Get the preamble length.
+        let preamble_length = self.send_command(&at::commands::p2p::GetPreambleLength {})?;
+        // Get the TX power.
+        let tx_power = self.send_command(&at::commands::p2p::GetTxPower {})?;
+        Ok(at::responses::p2p::P2Pparameters {
+            frequency: frequency.frequency,
+            spreading_factor: spreading_factor.spreading_factor,
+            bandwidth: bandwidth.bandwidth,
+            coderate: code_rate.code_rate,
+            preamblelength: preamble_length.preamble_length,
+            txpower: tx_power.tx_power,
+        })
+    }
+
+    /// Reads the modulation parameters shared with `LoRa` in FSK mode.
+    ///
+    /// The spreading factor and the code rate do not apply, the known ones are kept.
+    fn read_fsk_parameters(
+        &mut self,
+    ) -> Result<at::responses::p2p::P2Pparameters, nb::Error<Rui3Error>> {
+        // Get the frequency.
+        let frequency = self.send_command(&at::commands::p2p::GetP2PFrequency {})?;
+        // Get the bandwidth.
+        let bandwidth = self.send_command(&at::commands::p2p::GetP2PBandwidth {})?;
+        // Get the preamble length.
+        let preamble_length = self.send_command(&at::commands::p2p::GetPreambleLength {})?;
+        // Get the TX power.
+        let tx_power = self.send_command(&at::commands::p2p::GetTxPower {})?;
+        Ok(at::responses::p2p::P2Pparameters {
+            frequency: frequency.frequency,
+            spreading_factor: self.configuration.spreading_factor,
+            bandwidth: bandwidth.bandwidth,
+            coderate: self.configuration.code_rate.clone(),
+            preamblelength: preamble_length.preamble_length,
+            txpower: tx_power.tx_power,
+        })
+    }
+
+    /// Sends the commands of a configuration plan, recording each parameter set.
+    fn apply_plan<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        plan: &mut ConfigurationPlan<'_>,
+        new: &Configuration,
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        while let Some(step) = plan.next_step() {
+            match step {
+                ApplyStep::Send(command) => match self.send_command(&command) {
+                    Ok(_) => {
+                        for parameter in plan.applied() {
+                            self.configuration.copy_parameter(new, *parameter);
+                        }
+                    }
+                    Err(nb::Error::Other(error)) if plan.falls_back(&error) => {}
+                    Err(error) => return Err(error),
+                },
+                ApplyStep::WaitForRestart => self.wait_for_restart(clock)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a command, reporting RUI3 error lines as [`Rui3Error`]s.
+    fn send_command<A, const LEN: usize>(
+        &mut self,
+        command: &A,
+    ) -> Result<A::Response, nb::Error<Rui3Error>>
+    where
+        A: atat::AtatCmd<LEN>,
+    {
+        match self.client.send(&Checked(command)) {
+            Ok(CheckedResponse(response)) => response.map_err(nb::Error::Other),
+            Err(error) => Err(error.map(Rui3Error::Atat)),
+        }
+    }
+
+    /// Like [`Self::send_command`], retrying on timeouts.
+    fn send_command_retry<A, const LEN: usize>(
+        &mut self,
+        command: &A,
+    ) -> Result<A::Response, nb::Error<Rui3Error>>
+    where
+        A: atat::AtatCmd<LEN>,
+    {
+        match self.client.send_retry(&Checked(command)) {
+            Ok(CheckedResponse(response)) => response.map_err(nb::Error::Other),
+            Err(error) => Err(error.map(Rui3Error::Atat)),
+        }
+    }
+}
+
+/// Returns the delays between two `AT` while waiting for the module to boot,
+/// growing up to a limit, until [`BOOT_TIMEOUT`] elapses.
+fn boot_poll_delays() -> impl Iterator<Item = fugit::MillisDurationU32> {
+    let (first, max) = BOOT_POLL_DELAYS;
+    // Counted rather than read from a clock, as starting the timer may reset it.
+    let mut waited = fugit::MillisDurationU32::millis(0);
+    core::iter::successors(Some(first), move |delay| Some((*delay * 2).min(max))).take_while(
+        move |delay| {
+            waited += *delay;
+            waited < BOOT_TIMEOUT
+        },
+    )
+}
+
+/// Waits for `duration` with the timer of `clock`.
+fn wait<CLK, const TIMER_HZ: u32>(clock: &mut CLK, duration: fugit::MillisDurationU32)
+where
+    CLK: atat::clock::Clock<TIMER_HZ>,
+{
+    // A timer which does not start would never expire.
+    if let Err(error) = clock.start(duration.convert()) {
+        log::warn!("Failed to start the timer: {error:?}");
+        return;
+    }
+    if let Err(error) = nb::block!(clock.wait()) {
+        log::warn!("Failed to wait for the timer: {error:?}");
+    }
+}
+
+/// Copies the payload of a received packet into a caller-provided buffer.
+fn copy_packet<const TIMER_HZ: u32>(
+    packet: &ReceivedPacket<TIMER_HZ>,
+    buf: &mut [u8],
+) -> Result<PacketInfo<TIMER_HZ>, nb::Error<Rui3Error>> {
+    let target = buf
+        .get_mut(..packet.payload.len())
+        .ok_or(nb::Error::Other(Rui3Error::BufferTooSmall))?;
+    target.copy_from_slice(&packet.payload);
+    Ok(PacketInfo {
+        len: packet.payload.len(),
+        rssi: packet.rssi,
+        snr: packet.snr,
+        timestamp: packet.timestamp,
+    })
+}
+
+/// Encodes data as an uppercase hex string.
+///
+/// Returns `None` if the encoded data does not fit in `N` characters.
+fn encode_hex<const N: usize>(data: &[u8]) -> Option<atat::heapless::String<N>> {
+    let mut hex = atat::heapless::String::new();
+    for b in data {
+        write!(hex, "{b:02X}").ok()?;
+    }
+    Some(hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockClock, MockModule, RxState};
+    use alloc::{string::String, vec, vec::Vec};
+
+    fn radio() -> (MockModule, Rui3Radio<MockModule>) {
+        let module = MockModule::new();
+        (module.clone(), Rui3Radio::new(module))
+    }
+
+    #[test]
+    fn send_waits_for_the_end_of_the_transmission() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        radio.send(&[0xCA, 0xFE], &mut clock).unwrap();
+        radio.send(&[0x01], &mut clock).unwrap();
+
+        assert_eq!(module.transmitted(), vec![vec![0xCA, 0xFE], vec![0x01]]);
+        assert!(!module.is_transmitting());
+        assert_eq!(module.rx_state(), RxState::Continuous);
+        assert_eq!(
+            module.commands()[..3],
+            ["+PRECV=0", "+PSEND=CAFE", "+PRECV=65534"].map(String::from)
+        );
+    }
+
+    #[test]
+    fn send_keeps_the_packets_received_meanwhile() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.inject_packet(-71, 9, b"hello");
+        radio.send(&[0xCA, 0xFE], &mut clock).unwrap();
+        assert!(module.pending_events().is_empty());
+
+        let packet = radio.poll(&mut clock).unwrap();
+        assert_eq!(&packet.payload[..], b"hello");
+        assert_eq!((packet.rssi, packet.snr), (-71, 9));
+        assert_eq!(radio.poll(&mut clock), Err(nb::Error::WouldBlock));
+    }
+
+    #[test]
+    fn send_reports_module_errors() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.fail_next("+PSEND", &Rui3Error::BusyError);
+        assert_eq!(
+            radio.send(&[1, 2, 3], &mut clock),
+            Err(nb::Error::Other(Rui3Error::BusyError))
+        );
+        assert!(module.transmitted().is_empty());
+        assert_eq!(
+            radio.send(&[0; 256], &mut clock),
+            Err(nb::Error::Other(Rui3Error::ParamError))
+        );
+    }
+
+    #[test]
+    fn send_times_out() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.set_auto_tx_done(false);
+        assert_eq!(
+            radio.send(&[1, 2, 3], &mut clock),
+            Err(nb::Error::Other(Rui3Error::TxTimeout))
+        );
+
+        // The module stays busy until it reports the end of the transmission.
+        assert_eq!(
+            radio.send(&[1, 2, 3], &mut clock),
+            Err(nb::Error::Other(Rui3Error::BusyError))
+        );
+        module.set_auto_tx_done(true);
+        module.inject_event("+EVT:TXP2P DONE");
+        assert_eq!(radio.poll(&mut clock), Err(nb::Error::WouldBlock));
+        radio.send(&[1, 2, 3], &mut clock).unwrap();
+    }
+
+    #[test]
+    fn send_with_lbt_backs_off_while_the_channel_is_busy() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.set_busy_detections(2);
+        radio
+            .send_with_lbt(
+                &[0x42],
+                3,
+                fugit::MillisDurationU32::millis(100),
+                &mut clock,
+            )
+            .unwrap();
+
+        assert_eq!(module.transmitted(), vec![vec![0x42]]);
+        assert_eq!(module.parameter("+CAD").as_deref(), Some("0"));
+        let commands = module.commands();
+        assert_eq!(
+            commands.iter().filter(|c| c.starts_with("+PSEND")).count(),
+            3
+        );
+        // Two backoffs of at least half the maximum.
+        assert!(atat::clock::Clock::now(&mut clock).ticks() >= 100);
+        assert_eq!(module.rx_state(), RxState::Continuous);
+    }
+
+    #[test]
+    fn send_with_lbt_gives_up_after_the_last_attempt() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.set_busy_detections(5);
+        assert_eq!(
+            radio.send_with_lbt(&[0x42], 2, fugit::MillisDurationU32::millis(10), &mut clock),
+            Err(nb::Error::Other(Rui3Error::ChannelBusy))
+        );
+        assert!(module.transmitted().is_empty());
+        assert_eq!(module.rx_state(), RxState::Continuous);
+        // CAD is disabled again, plain sends ignore the busy channel.
+        assert_eq!(module.parameter("+CAD").as_deref(), Some("0"));
+        assert!(!radio.get_channel_activity_detection().unwrap());
+        radio.send(&[0x42], &mut clock).unwrap();
+        assert_eq!(module.transmitted(), vec![vec![0x42]]);
+    }
+
+    #[test]
+    fn send_with_lbt_keeps_channel_activity_detection_enabled_before() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        radio.set_channel_activity_detection(true).unwrap();
+        module.set_busy_detections(5);
+        assert_eq!(
+            radio.send_with_lbt(&[0x42], 2, fugit::MillisDurationU32::millis(10), &mut clock),
+            Err(nb::Error::Other(Rui3Error::ChannelBusy))
+        );
+        assert_eq!(module.parameter("+CAD").as_deref(), Some("1"));
+        assert_eq!(
+            radio.send(&[0x42], &mut clock),
+            Err(nb::Error::Other(Rui3Error::ChannelBusy))
+        );
+    }
+
+    #[test]
+    fn configure_sets_the_module_parameters() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        radio
+            .configure(
+                Configuration {
+                    frequency: 915_000_000,
+                    spreading_factor: 12,
+                    bandwidth: at::commands::p2p::Bandwidth::LoRa500KHz,
+                    tx_power: 20,
+                    ..Configuration::default()
+                },
+                &mut clock,
+            )
+            .unwrap();
+
+        assert_eq!(module.parameter("+PFREQ").as_deref(), Some("915000000"));
+        assert_eq!(module.parameter("+PSF").as_deref(), Some("12"));
+        assert_eq!(module.parameter("+PBW").as_deref(), Some("2"));
+        assert_eq!(module.parameter("+PTP").as_deref(), Some("20"));
+        assert_eq!(radio.configuration.frequency, 915_000_000);
+    }
+
+    #[test]
+    fn configure_only_sends_changed_parameters() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        let configuration = Configuration {
+            spreading_factor: 9,
+            encrypted: Encrypted::True,
+            encryption_key: "0123456789ABCDEF".into(),
+            sync_word: 0x1424,
+            iq_inversion: true,
+            ..Configuration::default()
+        };
+
+        let changes = radio.configure(configuration.clone(), &mut clock).unwrap();
+        assert_eq!(
+            changes,
+            [
+                ConfigurationParameter::SpreadingFactor,
+                ConfigurationParameter::EncryptionKey,
+                ConfigurationParameter::Encryption,
+                ConfigurationParameter::SyncWord,
+                ConfigurationParameter::IqInversion,
+            ]
+        );
+        assert_eq!(
+            module.parameter("+ENCKEY").as_deref(),
+            Some("0123456789ABCDEF")
+        );
+        assert_eq!(module.parameter("+ENCRY").as_deref(), Some("1"));
+        assert_eq!(module.parameter("+SYNCWORD").as_deref(), Some("1424"));
+        assert_eq!(module.parameter("+IQINVER").as_deref(), Some("1"));
+
+        // The mirror is used from now on.
+        module.clear_commands();
+        assert!(radio
+            .configure(configuration.clone(), &mut clock)
+            .unwrap()
+            .is_empty());
+        assert!(module.commands().is_empty());
+
+        let changes = radio
+            .configure(
+                Configuration {
+                    tx_power: 20,
+                    ..configuration
+                },
+                &mut clock,
+            )
+            .unwrap();
+        assert_eq!(changes, [ConfigurationParameter::TxPower]);
+        assert_eq!(module.commands(), ["+P2P=868000000:9:0:0:8:20"]);
+    }
+
+    #[test]
+    fn configure_reads_the_module_again_after_an_error() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.set_parameter("+PFREQ", "869525000");
+        module.fail_next("+SYMBOLTIMEOUT=10", &Rui3Error::BusyError);
+        let configuration = Configuration {
+            preamble_length: 16,
+            symbol_timeout: 10,
+            ..Configuration::default()
+        };
+        assert_eq!(
+            radio.configure(configuration.clone(), &mut clock),
+            Err(nb::Error::Other(Rui3Error::BusyError))
+        );
+        assert_eq!(module.parameter("+PFREQ").as_deref(), Some("868000000"));
+
+        module.clear_commands();
+        let changes = radio.configure(configuration, &mut clock).unwrap();
+        assert_eq!(changes, [ConfigurationParameter::SymbolTimeout]);
+        assert!(module.commands().contains(&String::from("+P2P=?")));
+    }
+
+    #[test]
+    fn diff_profile_compares_with_the_module() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.set_parameter("+PSF", "12");
+        let profile = Profile::new(
+            "track",
+            Configuration {
+                spreading_factor: 12,
+                tx_power: 22,
+                ..Configuration::default()
+            },
+        );
+
+        assert_eq!(
+            radio.diff_profile(&profile).unwrap(),
+            [ConfigurationParameter::TxPower]
+        );
+        assert_eq!(
+            radio.apply_profile(&profile, &mut clock).unwrap(),
+            [ConfigurationParameter::TxPower]
+        );
+        assert!(radio.diff_profile(&profile).unwrap().is_empty());
+    }
+
+    #[test]
+    fn configure_sets_modulation_parameters_at_once() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        radio.read_configuration().unwrap();
+        assert!(module.commands().contains(&String::from("+P2P=?")));
+        assert!(!module.commands().contains(&String::from("+PFREQ=?")));
+
+        module.clear_commands();
+        let changes = radio
+            .configure(
+                Configuration {
+                    frequency: 869_525_000,
+                    bandwidth: at::commands::p2p::Bandwidth::LoRa250KHz,
+                    code_rate: at::commands::p2p::CodeRate::PCR4_8,
+                    ..Configuration::default()
+                },
+                &mut clock,
+            )
+            .unwrap();
+        assert_eq!(
+            changes,
+            [
+                ConfigurationParameter::Frequency,
+                ConfigurationParameter::Bandwidth,
+                ConfigurationParameter::CodeRate,
+            ]
+        );
+        assert_eq!(module.commands(), ["+P2P=869525000:7:1:3:8:14"]);
+        assert_eq!(module.parameter("+PCR").as_deref(), Some("3"));
+
+        // Invalid values leave the module unchanged.
+        assert_eq!(
+            radio.configure(
+                Configuration {
+                    spreading_factor: 13,
+                    ..Configuration::default()
+                },
+                &mut clock
+            ),
+            Err(nb::Error::Other(Rui3Error::ParamError))
+        );
+        assert_eq!(module.parameter("+PFREQ").as_deref(), Some("869525000"));
+    }
+
+    #[test]
+    fn configure_falls_back_to_one_command_per_parameter() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.set_unsupported("+P2P");
+        let changes = radio
+            .configure(
+                Configuration {
+                    spreading_factor: 10,
+                    tx_power: 20,
+                    ..Configuration::default()
+                },
+                &mut clock,
+            )
+            .unwrap();
+        assert_eq!(
+            changes,
+            [
+                ConfigurationParameter::SpreadingFactor,
+                ConfigurationParameter::TxPower,
+            ]
+        );
+        // The combined command is only tried once.
+        let commands = module.commands();
+        assert_eq!(commands.iter().filter(|c| c.starts_with("+P2P")).count(), 1);
+        assert!(commands.ends_with(&[String::from("+PSF=10"), String::from("+PTP=20")]));
+    }
+
+    #[test]
+    fn configure_sets_fsk_parameters_in_fsk_mode() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        let fsk = Configuration {
+            working_mode: at::commands::p2p::WorkingMode::FskP2P,
+            bandwidth: at::commands::p2p::Bandwidth::Fsk(125_000),
+            fsk_bitrate: 9_600,
+            fsk_deviation: 10_000,
+            // Ignored in FSK mode.
+            spreading_factor: 12,
+            ..Configuration::default()
+        };
+        assert_eq!(
+            radio.configure(fsk.clone(), &mut clock).unwrap(),
+            [
+                ConfigurationParameter::WorkingMode,
+                ConfigurationParameter::Bandwidth,
+                ConfigurationParameter::FskBitrate,
+                ConfigurationParameter::FskDeviation,
+            ]
+        );
+        assert_eq!(module.parameter("+PBW").as_deref(), Some("125000"));
+        assert_eq!(module.parameter("+PBR").as_deref(), Some("9600"));
+        assert_eq!(module.parameter("+PFDEV").as_deref(), Some("10000"));
+        assert_eq!(module.parameter("+PSF").as_deref(), Some("7"));
+
+        let configuration = radio.read_configuration().unwrap();
+        assert_eq!(configuration.fsk_bitrate, 9_600);
+        assert_eq!(configuration.spreading_factor, 7);
+        assert!(fsk.diff(&configuration).is_empty());
+    }
+
+    #[test]
+    fn configure_waits_for_the_restart_after_a_working_mode_change() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        radio
+            .set_receiving_window(at::commands::p2p::ReceiveWindow::Continuous)
+            .unwrap();
+        module.clear_commands();
+
+        radio
+            .configure(
+                Configuration {
+                    working_mode: at::commands::p2p::WorkingMode::FskP2P,
+                    bandwidth: at::commands::p2p::Bandwidth::Fsk(125_000),
+                    ..Configuration::default()
+                },
+                &mut clock,
+            )
+            .unwrap();
+        // The module ignores the first polls while it restarts.
+        let commands = module.commands();
+        let restart = commands.iter().position(|c| c == "+NWM=2").unwrap();
+        assert_eq!(
+            commands[restart + 1..],
+            ["", "", "", "+PBW=125000"].map(String::from)
+        );
+        assert_eq!(module.parameter("+PBW").as_deref(), Some("125000"));
+        assert_eq!(radio.state(), RadioState::Idle);
+    }
+
+    #[test]
+    fn configure_reports_invalid_parameters() {
+        let (module, mut radio) = radio();
+        assert_eq!(
+            radio.set_spreading_factor(13),
+            Err(nb::Error::Other(Rui3Error::ParamError))
+        );
+        assert_eq!(module.parameter("+PSF").as_deref(), Some("7"));
+        assert_eq!(radio.get_frequency(), Ok(868_000_000));
+    }
+
+    #[test]
+    fn receive_returns_the_packet_and_its_signal_quality() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        clock.advance(fugit::TimerDurationU32::millis(100));
+        module.inject_event("+EVT:RXP2P RECEIVE TIMEOUT");
+        module.inject_packet(-71, 9, b"hello");
+
+        let packet = radio.receive(&mut clock).unwrap();
+        assert_eq!(&packet.payload[..], b"hello");
+        assert_eq!((packet.rssi, packet.snr), (-71, 9));
+        assert!(packet.timestamp.ticks() >= 100);
+        assert_eq!(module.rx_state(), RxState::Continuous);
+        assert_eq!(
+            radio.link_quality(),
+            Some(LinkQuality { rssi: -71, snr: 9 })
+        );
+    }
+
+    #[test]
+    fn receive_explicit_collects_the_packets_of_the_window() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.inject_packet(-50, 5, &[1]);
+        module.inject_packet(-60, 6, &[2]);
+
+        let packets = radio
+            .receive_explicit(
+                &at::commands::p2p::ReceiveWindow::Milliseconds(3000),
+                &mut clock,
+            )
+            .unwrap();
+        let payloads: Vec<_> = packets.iter().map(|p| p.payload[0]).collect();
+        assert_eq!(payloads, [1, 2]);
+        assert!(module.pending_events().is_empty());
+        assert_eq!(radio.link_quality_history().count(), 2);
+    }
+
+    #[test]
+    fn state_follows_commands_and_events() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        assert_eq!(radio.state(), RadioState::Idle);
+
+        radio
+            .set_receiving_window(at::commands::p2p::ReceiveWindow::Continuous)
+            .unwrap();
+        assert_eq!(radio.state(), RadioState::Rx);
+        // The module only accepts a new window once RX is stopped.
+        radio
+            .set_receiving_window(at::commands::p2p::ReceiveWindow::Milliseconds(500))
+            .unwrap();
+        assert_eq!(radio.state(), RadioState::RxWindow);
+        assert_eq!(
+            module.commands(),
+            ["+PRECV=65534", "+PRECV=0", "+PRECV=500"].map(String::from)
+        );
+        assert_eq!(radio.poll(&mut clock), Err(nb::Error::WouldBlock));
+        assert_eq!(radio.state(), RadioState::Idle);
+
+        module.set_auto_tx_done(false);
+        let pending = radio.start_send(&[1], &mut clock).unwrap();
+        assert_eq!(radio.state(), RadioState::Tx);
+        assert_eq!(
+            radio.set_receiving_window(at::commands::p2p::ReceiveWindow::OnePacket),
+            Err(nb::Error::Other(Rui3Error::Transmitting))
+        );
+        module.inject_event("+EVT:TXP2P DONE");
+        radio.poll_send(&pending, &mut clock).unwrap();
+        assert_eq!(radio.state(), RadioState::Rx);
+    }
+
+    #[test]
+    fn receive_is_rejected_while_transmitting() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.set_auto_tx_done(false);
+        radio.start_send(&[1], &mut clock).unwrap();
+        module.clear_commands();
+
+        assert_eq!(
+            radio.receive(&mut clock),
+            Err(nb::Error::Other(Rui3Error::Transmitting))
+        );
+        assert!(module.commands().is_empty());
+        assert!(module.is_transmitting());
+    }
+
+    #[test]
+    fn device_info_reads_the_module() {
+        let (module, mut radio) = radio();
+        module.set_parameter("+VER", "RUI_4.0.6_RAK3172-E");
+        module.set_parameter("+HWMODEL", "rak3172-sip");
+        radio.set_alias("pit lane").unwrap();
+        assert_eq!(
+            radio.set_alias("a very long alias"),
+            Err(nb::Error::Other(Rui3Error::ParamError))
+        );
+
+        let info = radio.device_info().unwrap();
+        assert_eq!(info.serial_number, "MOCK0000000001");
+        assert_eq!(info.firmware, "RUI_4.0.6_RAK3172-E");
+        assert_eq!(info.firmware_version, FirmwareVersion::parse("4.0.6"));
+        assert_eq!(info.model, Some(Model::Rak3172));
+        assert_eq!(info.alias, "pit lane");
+    }
+
+    #[test]
+    fn reset_waits_for_the_module_and_applies_the_configuration_again() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        radio
+            .configure(
+                Configuration {
+                    tx_power: 20,
+                    ..Configuration::default()
+                },
+                &mut clock,
+            )
+            .unwrap();
+        radio
+            .set_receiving_window(at::commands::p2p::ReceiveWindow::Continuous)
+            .unwrap();
+        // The module restarts before answering, then boots for a while.
+        module.fail_next("Z", &Rui3Error::Error);
+        module.fail_next("", &Rui3Error::BusyError);
+        module.fail_next("", &Rui3Error::BusyError);
+        module.set_parameter("+PTP", "14");
+        module.clear_commands();
+
+        radio.reset(&mut clock).unwrap();
+        assert_eq!(module.commands()[..4], ["Z", "", "", ""].map(String::from));
+        assert_eq!(module.parameter("+PTP").as_deref(), Some("20"));
+        assert_eq!(radio.state(), RadioState::Idle);
+    }
+
+    #[test]
+    fn factory_reset_applies_the_configuration_to_the_defaults() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        radio
+            .configure(
+                Configuration {
+                    spreading_factor: 12,
+                    ..Configuration::default()
+                },
+                &mut clock,
+            )
+            .unwrap();
+        radio.set_channel_activity_detection(true).unwrap();
+        radio.set_alias("car 7").unwrap();
+
+        radio.factory_reset(&mut clock).unwrap();
+        assert_eq!(module.parameter("+PSF").as_deref(), Some("12"));
+        assert_eq!(module.parameter("+CAD").as_deref(), Some("1"));
+        assert_eq!(module.parameter("+ALIAS").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn reset_gives_up_when_the_module_does_not_answer() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.set_unsupported("");
+
+        assert_eq!(
+            radio.reset(&mut clock),
+            Err(nb::Error::Other(Rui3Error::NotResponding))
+        );
+        // Growing delays, up to 5 seconds.
+        let attempts = module.commands().iter().filter(|c| c.is_empty()).count();
+        assert!((5..20).contains(&attempts), "{attempts} attempts");
+    }
+
+    #[test]
+    fn receive_into_copies_the_payload_and_its_metadata() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.inject_packet(-70, 4, &[1, 2, 3]);
+
+        let mut buf = [0; 8];
+        let info = radio.receive_into(&mut buf, &mut clock).unwrap();
+        assert_eq!(&buf[..info.len], &[1, 2, 3]);
+        assert_eq!((info.rssi, info.snr), (-70, 4));
+
+        module.inject_packet(-70, 4, &[1, 2, 3]);
+        assert_eq!(
+            radio.poll_into(&mut buf[..2], &mut clock),
+            Err(nb::Error::Other(Rui3Error::BufferTooSmall))
+        );
+        assert_eq!(
+            radio.poll_into(&mut buf, &mut clock),
+            Err(nb::Error::WouldBlock)
+        );
+    }
+
+    #[test]
+    fn receive_explicit_into_drops_packets_once_full() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        for i in 0..3 {
+            module.inject_packet(-50, 5, &[i]);
+        }
+
+        let mut packets: [ReceivedPacket<1000>; 2] = Default::default();
+        let count = radio
+            .receive_explicit_into(
+                &at::commands::p2p::ReceiveWindow::Milliseconds(3000),
+                &mut clock,
+                &mut packets,
+            )
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(&packets[1].payload[..], &[1]);
+    }
+
+    #[test]
+    fn poll_would_block_without_packets() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        assert_eq!(radio.poll(&mut clock), Err(nb::Error::WouldBlock));
+
+        module.inject_packet(-80, -3, &[7]);
+        let packet = radio.poll(&mut clock).unwrap();
+        assert_eq!(&packet.payload[..], &[7]);
+    }
 }
@@ -0,0 +1,159 @@
+//! Continuous reception of several packets.
+
+use crate::{at, ReceivedPacket, Rui3Error, Rui3Radio};
+
+/// Packets received while the module listens in continuous mode, returned by
+/// [`Rui3Radio::listen`].
+///
+/// The module keeps listening between packets: packets arriving while the previous
+/// one is handled are queued by the AT client and returned by the next call.
+/// Listening stops when the listener is stopped or dropped.
+///
+/// As an [`Iterator`], it blocks until the next packet and never ends.
+pub struct Listener<'a, C, CLK, const TIMER_HZ: u32>
+where
+    C: atat::AtatClient,
+    CLK: atat::clock::Clock<TIMER_HZ>,
+{
+    radio: &'a mut Rui3Radio<C>,
+    clock: &'a mut CLK,
+    listening: bool,
+}
+
+impl<'a, C, CLK, const TIMER_HZ: u32> Listener<'a, C, CLK, TIMER_HZ>
+where
+    C: atat::AtatClient,
+    CLK: atat::clock::Clock<TIMER_HZ>,
+{
+    /// Enables continuous RX and returns the listener.
+    pub(crate) fn start(
+        radio: &'a mut Rui3Radio<C>,
+        clock: &'a mut CLK,
+    ) -> Result<Self, nb::Error<Rui3Error>> {
+        // The module rejects `AT+PRECV` while already listening.
+        radio.restart_continuous_rx()?;
+        Ok(Self {
+            radio,
+            clock,
+            listening: true,
+        })
+    }
+
+    /// Returns the next received packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`nb::Error::WouldBlock`] if no packet has been received yet.
+    pub fn poll(&mut self) -> Result<ReceivedPacket<TIMER_HZ>, nb::Error<Rui3Error>> {
+        self.radio.poll(self.clock)
+    }
+
+    /// Stops listening.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Rui3Error`] if the module rejects the command or the AT client
+    /// fails.
+    pub fn stop(mut self) -> Result<(), nb::Error<Rui3Error>> {
+        self.listening = false;
+        self.radio.stop_listening()
+    }
+}
+
+impl<C, CLK, const TIMER_HZ: u32> Iterator for Listener<'_, C, CLK, TIMER_HZ>
+where
+    C: atat::AtatClient,
+    CLK: atat::clock::Clock<TIMER_HZ>,
+{
+    type Item = ReceivedPacket<TIMER_HZ>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.radio.wait_for_packet(self.clock))
+    }
+}
+
+impl<C, CLK, const TIMER_HZ: u32> Drop for Listener<'_, C, CLK, TIMER_HZ>
+where
+    C: atat::AtatClient,
+    CLK: atat::clock::Clock<TIMER_HZ>,
+{
+    fn drop(&mut self) {
+        if self.listening {
+            if let Err(error) = self.radio.stop_listening() {
+                log::warn!("Failed to stop listening: {error:?}");
+            }
+        }
+    }
+}
+
+impl<C> Rui3Radio<C>
+where
+    C: atat::AtatClient,
+{
+    /// Disables RX.
+    fn stop_listening(&mut self) -> Result<(), nb::Error<Rui3Error>> {
+        self.start_rx(at::commands::p2p::ReceiveWindow::StopListening)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{Channel, MockClock, MockModule, RxState};
+    use crate::Rui3Radio;
+    use alloc::{string::String, vec::Vec};
+
+    fn precv_count(module: &MockModule) -> usize {
+        module
+            .commands()
+            .iter()
+            .filter(|command| command.starts_with("+PRECV"))
+            .count()
+    }
+
+    #[test]
+    fn yields_packets_without_restarting_rx() {
+        let module = MockModule::new();
+        let mut radio = Rui3Radio::new(module.clone());
+        let mut clock = MockClock::<1000>::new();
+        for i in 0..3 {
+            module.inject_packet(-70, 8, &[i]);
+        }
+
+        let payloads: Vec<u8> = radio
+            .listen(&mut clock)
+            .unwrap()
+            .take(3)
+            .map(|packet| packet.payload[0])
+            .collect();
+
+        assert_eq!(payloads, [0, 1, 2]);
+        // Started once, stopped when dropped.
+        assert_eq!(
+            module.commands(),
+            ["+PRECV=0", "+PRECV=65534", "+PRECV=0"].map(String::from)
+        );
+        assert_eq!(module.rx_state(), RxState::Off);
+    }
+
+    #[test]
+    fn keeps_packets_received_between_calls() {
+        let channel = Channel::new();
+        let mut car = Rui3Radio::new(channel.attach());
+        let pit_module = channel.attach();
+        let mut pit = Rui3Radio::new(pit_module.clone());
+        let mut clock = MockClock::<1000>::new();
+        let mut car_clock = MockClock::<1000>::new();
+
+        let mut listener = pit.listen(&mut clock).unwrap();
+        assert_eq!(listener.poll(), Err(nb::Error::WouldBlock));
+        car.send(&[1], &mut car_clock).unwrap();
+        car.send(&[2], &mut car_clock).unwrap();
+        assert_eq!(&listener.next().unwrap().payload[..], &[1]);
+        assert_eq!(&listener.poll().unwrap().payload[..], &[2]);
+        assert_eq!(precv_count(&pit_module), 2);
+
+        listener.stop().unwrap();
+        assert_eq!(precv_count(&pit_module), 3);
+        assert_eq!(pit_module.rx_state(), RxState::Off);
+    }
+}
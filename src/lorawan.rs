@@ -0,0 +1,496 @@
+//! `LoRaWAN` operations of [`Rui3Radio`].
+//!
+//! The module must already be in [`WorkingMode::LoRaWan`], see
+//! [`Rui3Radio::set_network_working_mode`].
+//!
+//! [`WorkingMode::LoRaWan`]: crate::at::commands::p2p::WorkingMode::LoRaWan
+
+use crate::{
+    at,
+    at::commands::{
+        lorawan::Band,
+        p2p::{Bandwidth, WorkingMode},
+    },
+    encode_hex, Configuration, Rui3Error, Rui3Radio,
+};
+
+/// Delay after the last join request before the module gives up on the join accept,
+/// which comes at the latest in the second receive window.
+const JOIN_ACCEPT_DELAY: fugit::MillisDurationU32 = fugit::MillisDurationU32::millis(6_000);
+/// Delay after each transmission of an uplink before the module gives up on a
+/// downlink: the second receive window opens 2 s after the uplink, and lasts up to a
+/// second.
+const RX_WINDOWS_DELAY: fugit::MillisDurationU32 = fugit::MillisDurationU32::millis(3_000);
+/// Bytes added by `LoRaWAN` to the application payload: MAC header, frame header,
+/// port and MIC.
+const FRAME_OVERHEAD: usize = 13;
+
+/// How the module retries joining the network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JoinRetryPolicy {
+    /// Seconds between join attempts, from 7 to 255.
+    pub interval: u8,
+    /// Number of join attempts, at least 1.
+    pub attempts: u8,
+}
+
+/// Default trait implementation for `JoinRetryPolicy`.
+impl Default for JoinRetryPolicy {
+    fn default() -> Self {
+        Self {
+            interval: 8,
+            attempts: 3,
+        }
+    }
+}
+
+/// Outcome of an uplink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UplinkStatus {
+    /// An unconfirmed uplink has been transmitted.
+    Sent,
+    /// A confirmed uplink has been acknowledged by the network.
+    Confirmed,
+    /// A confirmed uplink has not been acknowledged by the network.
+    NotConfirmed,
+}
+
+impl<C> Rui3Radio<C>
+where
+    C: atat::AtatClient,
+{
+    /// Joins the network with over-the-air activation.
+    ///
+    /// Sets the keys, starts joining and waits for `+EVT:JOINED` or
+    /// `+EVT:JOIN_FAILED_*` after the last attempt.
+    ///
+    /// Gives up once every attempt had time to complete: `interval` times
+    /// `attempts` seconds, plus the join accept delay and the margin set with
+    /// [`set_tx_timeout_margin`].
+    ///
+    /// [`set_tx_timeout_margin`]: Rui3Radio::set_tx_timeout_margin
+    ///
+    /// # Arguments
+    ///
+    /// * `dev_eui` - The device EUI.
+    /// * `app_eui` - The application EUI.
+    /// * `app_key` - The application key.
+    /// * `retry_policy` - How the module retries joining.
+    /// * `clock` - The clock used to measure the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// radio_client.join_otaa(dev_eui, app_eui, app_key, &JoinRetryPolicy::default(), &mut clock)?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::JoinFailed`] if every join attempt failed,
+    /// [`Rui3Error::JoinTimeout`] if the module did not report the outcome in time,
+    /// [`Rui3Error::ParamError`] if `retry_policy.attempts` is 0, or another
+    /// [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub fn join_otaa<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        dev_eui: &[u8; 8],
+        app_eui: &[u8; 8],
+        app_key: &[u8; 16],
+        retry_policy: &JoinRetryPolicy,
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        // The module would retry forever.
+        if retry_policy.attempts == 0 {
+            return Err(nb::Error::Other(Rui3Error::ParamError));
+        }
+
+        self.send_command(&at::commands::lorawan::SetJoinMode {
+            mode: at::commands::lorawan::JoinMode::Otaa,
+        })?;
+        self.send_command(&at::commands::lorawan::SetDevEui {
+            dev_eui: hex_key(dev_eui)?,
+        })?;
+        self.send_command(&at::commands::lorawan::SetAppEui {
+            app_eui: hex_key(app_eui)?,
+        })?;
+        self.send_command(&at::commands::lorawan::SetAppKey {
+            app_key: hex_key(app_key)?,
+        })?;
+
+        log::debug!("Joining with policy: {retry_policy:?}");
+        self.send_command(&at::commands::lorawan::Join {
+            join: true,
+            auto_join: false,
+            interval: retry_policy.interval,
+            attempts: retry_policy.attempts,
+        })?;
+
+        let attempts = fugit::MillisDurationU32::secs(u32::from(retry_policy.interval))
+            * u32::from(retry_policy.attempts);
+        let deadline =
+            clock.now() + (attempts + JOIN_ACCEPT_DELAY + self.tx_timeout_margin).convert();
+        let mut failures = 0;
+        while clock.now() < deadline {
+            match self.check_event() {
+                Some(at::urc::URCMessages::Joined) => return Ok(()),
+                // The module reports every failed attempt.
+                Some(at::urc::URCMessages::JoinFailed) => {
+                    failures += 1;
+                    if failures >= retry_policy.attempts {
+                        return Err(nb::Error::Other(Rui3Error::JoinFailed));
+                    }
+                }
+                _ => {}
+            }
+        }
+        log::debug!("Join timed out after {failures} failed attempts");
+        Err(nb::Error::Other(Rui3Error::JoinTimeout))
+    }
+
+    /// Activates the device by personalization.
+    ///
+    /// Sets the session keys; the module can send uplinks right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `dev_addr` - The device address.
+    /// * `app_s_key` - The application session key.
+    /// * `nwk_s_key` - The network session key.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// radio_client.join_abp(dev_addr, app_s_key, nwk_s_key)?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub fn join_abp(
+        &mut self,
+        dev_addr: &[u8; 4],
+        app_s_key: &[u8; 16],
+        nwk_s_key: &[u8; 16],
+    ) -> Result<(), nb::Error<Rui3Error>> {
+        self.send_command(&at::commands::lorawan::SetJoinMode {
+            mode: at::commands::lorawan::JoinMode::Abp,
+        })?;
+        self.send_command(&at::commands::lorawan::SetDevAddr {
+            dev_addr: hex_key(dev_addr)?,
+        })?;
+        self.send_command(&at::commands::lorawan::SetAppSKey {
+            app_s_key: hex_key(app_s_key)?,
+        })?;
+        self.send_command(&at::commands::lorawan::SetNwkSKey {
+            nwk_s_key: hex_key(nwk_s_key)?,
+        })?;
+        Ok(())
+    }
+
+    #[allow(missing_doc_code_examples)]
+    #[allow(clippy::missing_errors_doc)]
+    /// Returns whether the module has joined the network.
+    pub fn is_joined(&mut self) -> Result<bool, nb::Error<Rui3Error>> {
+        let status = self.send_command(&at::commands::lorawan::GetJoinStatus {})?;
+        Ok(status.status == at::commands::lorawan::JoinStatus::Joined)
+    }
+
+    /// Sends an uplink and waits for its outcome.
+    ///
+    /// Waits for `+EVT:TX_DONE` for unconfirmed uplinks, and for
+    /// `+EVT:SEND_CONFIRMED_OK` or `+EVT:SEND_CONFIRMED_FAILED` for confirmed ones.
+    /// Whichever the module reports is returned, even if it does not match
+    /// `confirmed`.
+    ///
+    /// Gives up after the time on air of the frame at the data rate of the module
+    /// and its receive windows, for each transmission, plus the margin set with
+    /// [`set_tx_timeout_margin`]. Confirmed uplinks are sent up to `AT+RETY` more
+    /// times.
+    ///
+    /// [`set_tx_timeout_margin`]: Rui3Radio::set_tx_timeout_margin
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - The application port, from 1 to 223.
+    /// * `data` - The data to send.
+    /// * `confirmed` - Whether the network must acknowledge the uplink.
+    /// * `clock` - The clock used to measure the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```compile_fail
+    /// let status = radio_client.send_uplink(2, &data, true, &mut clock)?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::NoNetworkJoined`] if the module has not joined yet,
+    /// [`Rui3Error::ParamError`] if `data` is too long, [`Rui3Error::TxTimeout`] if
+    /// the module did not report the outcome in time, or another [`Rui3Error`] if the
+    /// module rejects a command or the AT client fails.
+    pub fn send_uplink<CLK, const TIMER_HZ: u32>(
+        &mut self,
+        port: u8,
+        data: &[u8],
+        confirmed: bool,
+        clock: &mut CLK,
+    ) -> Result<UplinkStatus, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        let mode = if confirmed {
+            at::commands::lorawan::ConfirmMode::Confirmed
+        } else {
+            at::commands::lorawan::ConfirmMode::Unconfirmed
+        };
+        self.send_command(&at::commands::lorawan::SetConfirmMode { mode })?;
+
+        let band = self.send_command(&at::commands::lorawan::GetBand {})?;
+        // With ADR, the network may have changed the data rate since the last uplink.
+        let data_rate = self.send_command(&at::commands::lorawan::GetDataRate {})?;
+        // The module only retransmits confirmed uplinks.
+        let transmissions = if confirmed {
+            let retransmissions =
+                self.send_command(&at::commands::lorawan::GetRetransmissions {})?;
+            u32::from(retransmissions.retransmissions) + 1
+        } else {
+            1
+        };
+
+        let payload = encode_hex(data).ok_or(nb::Error::Other(Rui3Error::ParamError))?;
+        self.send_command_retry(&at::commands::lorawan::SendData { port, payload })?;
+
+        let transmission =
+            uplink_time_on_air(&band.band, data_rate.data_rate, data.len() + FRAME_OVERHEAD)
+                + RX_WINDOWS_DELAY.convert();
+        let timeout = transmission * transmissions + self.tx_timeout_margin.convert();
+        log::debug!("Waiting up to {timeout} for the uplink");
+        let deadline = clock.now() + timeout.convert();
+        while clock.now() < deadline {
+            // `AT+CFM` may not match what the module does, accept either outcome.
+            match self.check_event() {
+                Some(at::urc::URCMessages::TxDone) => return Ok(UplinkStatus::Sent),
+                Some(at::urc::URCMessages::SendConfirmedOk) => {
+                    return Ok(UplinkStatus::Confirmed);
+                }
+                Some(at::urc::URCMessages::SendConfirmedFailed) => {
+                    return Ok(UplinkStatus::NotConfirmed);
+                }
+                _ => {}
+            }
+        }
+        log::debug!("Uplink timed out");
+        Err(nb::Error::Other(Rui3Error::TxTimeout))
+    }
+}
+
+/// Returns how long a frame of `frame_len` bytes occupies the channel at a data rate
+/// of a band, as defined by the `LoRaWAN` regional parameters.
+///
+/// Data rates the band does not define are assumed to be the slowest one.
+fn uplink_time_on_air(band: &Band, data_rate: u8, frame_len: usize) -> fugit::MicrosDurationU32 {
+    // Bands with fixed channel plans use 500 kHz channels from DR8.
+    let fixed_plan = matches!(band, Band::US915 | Band::AU915 | Band::LA915);
+    let (spreading_factor, bandwidth) = match (band, data_rate) {
+        (Band::US915, 0..=3) => (10 - data_rate, Bandwidth::LoRa125KHz),
+        (Band::US915, 4) | (Band::AU915 | Band::LA915, 6) => (8, Bandwidth::LoRa500KHz),
+        (_, 8..=13) if fixed_plan => (20 - data_rate, Bandwidth::LoRa500KHz),
+        (Band::US915, _) => (10, Bandwidth::LoRa125KHz),
+        (_, 0..=5) => (12 - data_rate, Bandwidth::LoRa125KHz),
+        (_, 6) if !fixed_plan => (7, Bandwidth::LoRa250KHz),
+        (_, 7) if !fixed_plan => {
+            // 50 kbit/s FSK with 5 bytes of preamble.
+            let configuration = Configuration {
+                working_mode: WorkingMode::FskP2P,
+                preamble_length: 5,
+                fsk_bitrate: 50_000,
+                ..Configuration::default()
+            };
+            return configuration.time_on_air(frame_len);
+        }
+        _ => (12, Bandwidth::LoRa125KHz),
+    };
+    let configuration = Configuration {
+        spreading_factor,
+        bandwidth,
+        ..Configuration::default()
+    };
+    configuration.time_on_air(frame_len)
+}
+
+/// Encodes a key as the hex string expected by the module.
+fn hex_key<const N: usize>(key: &[u8]) -> Result<atat::heapless::String<N>, nb::Error<Rui3Error>> {
+    encode_hex(key).ok_or(nb::Error::Other(Rui3Error::ParamError))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockClock, MockModule};
+    use alloc::string::String;
+
+    const DEV_EUI: [u8; 8] = [0x70, 0xB3, 0xD5, 0x7E, 0xD0, 0x00, 0x00, 0x01];
+    const APP_EUI: [u8; 8] = [0; 8];
+    const APP_KEY: [u8; 16] = [0x2B; 16];
+
+    fn radio() -> (MockModule, Rui3Radio<MockModule>) {
+        let module = MockModule::new();
+        module.set_parameter("+NWM", "1");
+        (module.clone(), Rui3Radio::new(module))
+    }
+
+    #[test]
+    fn join_otaa_waits_for_the_join_accept() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+
+        radio
+            .join_otaa(
+                &DEV_EUI,
+                &APP_EUI,
+                &APP_KEY,
+                &JoinRetryPolicy::default(),
+                &mut clock,
+            )
+            .unwrap();
+        assert_eq!(
+            module.parameter("+DEVEUI").as_deref(),
+            Some("70B3D57ED0000001")
+        );
+        assert!(radio.is_joined().unwrap());
+    }
+
+    #[test]
+    fn join_otaa_fails_after_the_last_attempt() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.set_join_failures(3);
+
+        assert_eq!(
+            radio.join_otaa(
+                &DEV_EUI,
+                &APP_EUI,
+                &APP_KEY,
+                &JoinRetryPolicy::default(),
+                &mut clock
+            ),
+            Err(nb::Error::Other(Rui3Error::JoinFailed))
+        );
+        assert_eq!(
+            radio.join_otaa(
+                &DEV_EUI,
+                &APP_EUI,
+                &APP_KEY,
+                &JoinRetryPolicy {
+                    interval: 8,
+                    attempts: 0
+                },
+                &mut clock
+            ),
+            Err(nb::Error::Other(Rui3Error::ParamError))
+        );
+    }
+
+    #[test]
+    fn join_otaa_times_out_without_events() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        // Only two of the three failures are reported.
+        module.set_join_failures(3);
+        module.set_lost_events(1);
+
+        assert_eq!(
+            radio.join_otaa(
+                &DEV_EUI,
+                &APP_EUI,
+                &APP_KEY,
+                &JoinRetryPolicy::default(),
+                &mut clock
+            ),
+            Err(nb::Error::Other(Rui3Error::JoinTimeout))
+        );
+    }
+
+    #[test]
+    fn uplink_time_on_air_follows_the_band() {
+        // SF12 and SF7 at 125 kHz.
+        assert_eq!(
+            uplink_time_on_air(&Band::EU868, 0, 23).to_micros(),
+            1_482_752
+        );
+        assert_eq!(uplink_time_on_air(&Band::EU868, 5, 23).to_micros(), 61_696);
+        // SF10 at 125 kHz, SF8 at 500 kHz.
+        assert_eq!(uplink_time_on_air(&Band::US915, 0, 23).to_micros(), 370_688);
+        assert_eq!(uplink_time_on_air(&Band::US915, 4, 23).to_micros(), 28_288);
+        assert_eq!(
+            uplink_time_on_air(&Band::AU915, 6, 23),
+            uplink_time_on_air(&Band::US915, 4, 23)
+        );
+        // FSK at 50 kbit/s: (5 + 3 + 1 + 23 + 2) bytes.
+        assert_eq!(uplink_time_on_air(&Band::EU868, 7, 23).to_micros(), 5_440);
+        // Undefined data rates are the slowest ones.
+        assert_eq!(
+            uplink_time_on_air(&Band::EU868, 15, 23),
+            uplink_time_on_air(&Band::EU868, 0, 23)
+        );
+    }
+
+    #[test]
+    fn send_uplink_waits_for_every_transmission() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.set_parameter("+NJS", "1");
+        module.set_parameter("+DR", "0");
+        module.set_parameter("+RETY", "2");
+        module.set_lost_events(1);
+
+        assert_eq!(
+            radio.send_uplink(2, &[1; 10], true, &mut clock),
+            Err(nb::Error::Other(Rui3Error::TxTimeout))
+        );
+        // Three transmissions at SF12 with their receive windows.
+        let transmission = uplink_time_on_air(&Band::EU868, 0, 23).to_millis() + 3_000;
+        assert!(atat::clock::Clock::now(&mut clock).ticks() >= 3 * transmission);
+    }
+
+    #[test]
+    fn send_uplink_reports_unconfirmed_uplinks() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        radio.join_abp(&[1; 4], &[2; 16], &[3; 16]).unwrap();
+        module.set_parameter("+NJS", "1");
+
+        assert_eq!(
+            radio.send_uplink(2, &[1, 2], false, &mut clock),
+            Ok(UplinkStatus::Sent)
+        );
+        assert_eq!(
+            module.commands().last().map(String::as_str),
+            Some("+SEND=2:0102")
+        );
+    }
+
+    #[test]
+    fn send_uplink_reports_confirmed_uplinks() {
+        let (module, mut radio) = radio();
+        let mut clock = MockClock::<1000>::new();
+        module.set_parameter("+NJS", "1");
+
+        assert_eq!(
+            radio.send_uplink(2, &[1], true, &mut clock),
+            Ok(UplinkStatus::Confirmed)
+        );
+        module.set_uplink_acknowledged(false);
+        assert_eq!(
+            radio.send_uplink(2, &[1], true, &mut clock),
+            Ok(UplinkStatus::NotConfirmed)
+        );
+        module.set_lost_events(1);
+        assert_eq!(
+            radio.send_uplink(2, &[1], true, &mut clock),
+            Err(nb::Error::Other(Rui3Error::TxTimeout))
+        );
+    }
+}
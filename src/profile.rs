@@ -0,0 +1,357 @@
+//! Named radio configurations.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::Configuration;
+
+/// A named [`Configuration`], such as a long range or a bench test setup.
+///
+/// With the `serde` feature, the configuration fields are stored next to the name.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Profile {
+    /// The name of the profile.
+    pub name: String,
+    /// The configuration of the radio.
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub configuration: Configuration,
+}
+
+impl Profile {
+    /// Creates a profile with the given name.
+    #[must_use]
+    pub fn new(name: &str, configuration: Configuration) -> Self {
+        Self {
+            name: name.into(),
+            configuration,
+        }
+    }
+}
+
+/// A set of [`Profile`]s with distinct names.
+///
+/// With the `serde` feature, it is stored as a sequence of profiles.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Profiles {
+    profiles: Vec<Profile>,
+}
+
+impl Profiles {
+    /// Creates an empty set of profiles.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            profiles: Vec::new(),
+        }
+    }
+
+    /// Adds a profile, returning the one it replaces if the name was taken.
+    pub fn insert(&mut self, profile: Profile) -> Option<Profile> {
+        if let Some(existing) = self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            return Some(core::mem::replace(existing, profile));
+        }
+        self.profiles.push(profile);
+        None
+    }
+
+    /// Returns the profile with the given name.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// Removes and returns the profile with the given name.
+    pub fn remove(&mut self, name: &str) -> Option<Profile> {
+        let index = self.profiles.iter().position(|p| p.name == name)?;
+        Some(self.profiles.remove(index))
+    }
+
+    /// Returns the profiles, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &Profile> {
+        self.profiles.iter()
+    }
+
+    /// Returns the number of profiles.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.profiles.len()
+    }
+
+    /// Returns `true` if there are no profiles.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+}
+
+/// `serde(with)` modules for the [`Configuration`] fields whose AT types do
+/// not suit configuration files.
+#[cfg(feature = "serde")]
+pub mod fields {
+
+    pub mod working_mode {
+        use crate::at::commands::p2p::WorkingMode;
+        use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            mode: &WorkingMode,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u8(mode.clone() as u8)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<WorkingMode, D::Error> {
+            let value = u8::deserialize(deserializer)?;
+            WorkingMode::from_value(value).ok_or_else(|| D::Error::custom("invalid working mode"))
+        }
+    }
+
+    pub mod bandwidth {
+        use crate::at::commands::p2p::Bandwidth;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            bandwidth: &Bandwidth,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u32(bandwidth.value())
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Bandwidth, D::Error> {
+            u32::deserialize(deserializer).map(Bandwidth::from_value)
+        }
+    }
+
+    pub mod code_rate {
+        use crate::at::commands::p2p::CodeRate;
+        use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            code_rate: &CodeRate,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u8(code_rate.clone() as u8)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<CodeRate, D::Error> {
+            let value = u8::deserialize(deserializer)?;
+            CodeRate::from_value(value).ok_or_else(|| D::Error::custom("invalid code rate"))
+        }
+    }
+
+    pub mod encrypted {
+        use crate::at::commands::p2p::Encrypted;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            encrypted: &Encrypted,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bool(*encrypted == Encrypted::True)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Encrypted, D::Error> {
+            Ok(if bool::deserialize(deserializer)? {
+                Encrypted::True
+            } else {
+                Encrypted::False
+            })
+        }
+    }
+
+    pub mod encryption_key {
+        use alloc::string::String;
+        use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            key: &atat::heapless::String<16>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(key)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<atat::heapless::String<16>, D::Error> {
+            let key = String::deserialize(deserializer)?;
+            if (key.len() != 16 && !key.is_empty()) || !key.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(D::Error::custom("the encryption key must be 16 hex digits"));
+            }
+            Ok(key.as_str().into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "serde")]
+    use alloc::format;
+
+    use super::*;
+    use crate::{at, ConfigurationParameter};
+
+    #[test]
+    fn insert_replaces_profiles_with_the_same_name() {
+        let mut profiles = Profiles::new();
+        profiles.insert(Profile::new("bench", Configuration::default()));
+        profiles.insert(Profile::new(
+            "track",
+            Configuration {
+                spreading_factor: 12,
+                ..Configuration::default()
+            },
+        ));
+        let replaced = profiles.insert(Profile::new(
+            "bench",
+            Configuration {
+                tx_power: 5,
+                ..Configuration::default()
+            },
+        ));
+
+        assert_eq!(replaced.unwrap().configuration.tx_power, 14);
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles.get("bench").unwrap().configuration.tx_power, 5);
+        assert_eq!(
+            profiles
+                .remove("track")
+                .unwrap()
+                .configuration
+                .spreading_factor,
+            12
+        );
+        assert!(profiles.get("track").is_none());
+    }
+
+    #[test]
+    fn diff_lists_the_differing_parameters_in_order() {
+        let current = Configuration {
+            encryption_key: "0123456789ABCDEF".into(),
+            ..Configuration::default()
+        };
+        let profile = Configuration {
+            tx_power: 20,
+            working_mode: at::commands::p2p::WorkingMode::FskP2P,
+            encryption_key: "0123456789abcdef".into(),
+            ..Configuration::default()
+        };
+
+        assert_eq!(
+            profile.diff(&current),
+            [
+                ConfigurationParameter::WorkingMode,
+                ConfigurationParameter::TxPower
+            ]
+        );
+        assert!(current.diff(&current).is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn fields_use_the_at_values() {
+        use serde::de::{value::Error, IntoDeserializer};
+
+        assert_eq!(
+            fields::bandwidth::deserialize(IntoDeserializer::<Error>::into_deserializer(2_u32)),
+            Ok(at::commands::p2p::Bandwidth::LoRa500KHz)
+        );
+        assert!(
+            fields::code_rate::deserialize(IntoDeserializer::<Error>::into_deserializer(4_u8))
+                .is_err()
+        );
+        assert!(
+            fields::encryption_key::deserialize(IntoDeserializer::<Error>::into_deserializer(
+                "0123"
+            ))
+            .is_err()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    fn fsk_configuration() -> Configuration {
+        Configuration {
+            working_mode: at::commands::p2p::WorkingMode::FskP2P,
+            bandwidth: at::commands::p2p::Bandwidth::Fsk(125_000),
+            code_rate: at::commands::p2p::CodeRate::PCR4_8,
+            encrypted: at::commands::p2p::Encrypted::True,
+            encryption_key: "0123456789ABCDEF".into(),
+            fsk_bitrate: 9_600,
+            fsk_deviation: 10_000,
+            sync_word: 0x1424,
+            iq_inversion: true,
+            symbol_timeout: 12,
+            ..Configuration::default()
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn configuration_round_trips() {
+        let configuration = fsk_configuration();
+        let json = serde_json::to_value(&configuration).unwrap();
+        assert_eq!(json["working_mode"], 2);
+        assert_eq!(json["bandwidth"], 125_000);
+        assert_eq!(json["code_rate"], 3);
+        assert_eq!(json["encrypted"], true);
+        assert_eq!(json["encryption_key"], "0123456789ABCDEF");
+        assert_eq!(json["fsk_bitrate"], 9_600);
+
+        let decoded: Configuration = serde_json::from_value(json).unwrap();
+        assert_eq!(format!("{decoded:?}"), format!("{configuration:?}"));
+
+        let lora = Configuration {
+            bandwidth: at::commands::p2p::Bandwidth::LoRa500KHz,
+            encryption_key: "".into(),
+            ..Configuration::default()
+        };
+        let text = serde_json::to_string(&lora).unwrap();
+        let decoded: Configuration = serde_json::from_str(&text).unwrap();
+        assert_eq!(format!("{decoded:?}"), format!("{lora:?}"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn configuration_rejects_invalid_values() {
+        let mut json = serde_json::to_value(Configuration::default()).unwrap();
+        json["working_mode"] = 3.into();
+        assert!(serde_json::from_value::<Configuration>(json).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn profiles_round_trip() {
+        let mut profiles = Profiles::new();
+        profiles.insert(Profile::new("bench", Configuration::default()));
+        profiles.insert(Profile::new("track", fsk_configuration()));
+
+        let json = serde_json::to_value(&profiles).unwrap();
+        // A sequence of profiles, the fields next to the name.
+        assert_eq!(json[1]["name"], "track");
+        assert_eq!(json[1]["fsk_deviation"], 10_000);
+
+        let decoded: Profiles = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            decoded.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+            ["bench", "track"]
+        );
+        for (decoded, profile) in decoded.iter().zip(profiles.iter()) {
+            assert_eq!(format!("{decoded:?}"), format!("{profile:?}"));
+        }
+
+        let profile: Profile =
+            serde_json::from_str(&serde_json::to_string(profiles.get("track").unwrap()).unwrap())
+                .unwrap();
+        assert_eq!(profile.name, "track");
+        assert!(profile.configuration.diff(&fsk_configuration()).is_empty());
+    }
+}
@@ -0,0 +1,422 @@
+//! Half-duplex scheduling of transmissions around reception.
+
+use crate::{at, PendingSend, RadioState, ReceivedPacket, Rui3Error, Rui3Radio};
+
+/// Owns a [`Rui3Radio`] and interleaves queued transmissions with reception.
+///
+/// RUI3 modules reject `AT+PSEND` and `AT+PRECV` while receiving, so every
+/// transmission must stop RX first and enable it again afterwards. The scheduler
+/// follows the state of the module tracked by the radio and does so for each
+/// packet of its queue of up to `N` packets, one at a time, between RX windows.
+/// While listening, RX is enabled again after every transmission, also when a
+/// command fails: if the module rejects it, it is tried again at the next
+/// [`poll`].
+///
+/// Everything happens in [`poll`], which must be called regularly.
+///
+/// [`poll`]: Scheduler::poll
+///
+/// # Example
+///
+/// ```compile_fail
+/// let mut scheduler: Scheduler<_, 8, 1_000> = Scheduler::new(radio_client);
+/// scheduler.listen()?;
+/// loop {
+///     if let Some(lap) = next_lap() {
+///         scheduler.queue(&lap)?;
+///     }
+///     match scheduler.poll(&mut clock) {
+///         Ok(packet) => handle(packet),
+///         Err(nb::Error::WouldBlock) => {}
+///         Err(nb::Error::Other(error)) => log::warn!("{error}"),
+///     }
+/// }
+/// ```
+pub struct Scheduler<C, const N: usize, const TIMER_HZ: u32>
+where
+    C: atat::AtatClient,
+{
+    radio: Rui3Radio<C>,
+    /// Whether RX is enabled whenever the module is not transmitting.
+    listening: bool,
+    queue: atat::heapless::Deque<at::urc::Payload, N>,
+    /// The transmission in progress.
+    pending: Option<PendingSend<TIMER_HZ>>,
+    /// Instant after which the RX window is over, even if the module did not say so.
+    window_end: Option<fugit::TimerInstantU32<TIMER_HZ>>,
+}
+
+impl<C, const N: usize, const TIMER_HZ: u32> Scheduler<C, N, TIMER_HZ>
+where
+    C: atat::AtatClient,
+{
+    /// Creates a scheduler for a radio which is neither receiving nor transmitting.
+    pub const fn new(radio: Rui3Radio<C>) -> Self {
+        Self {
+            radio,
+            listening: false,
+            queue: atat::heapless::Deque::new(),
+            pending: None,
+            window_end: None,
+        }
+    }
+
+    /// Returns the state of the module.
+    pub const fn state(&self) -> RadioState {
+        self.radio.state()
+    }
+
+    /// Returns the radio, e.g. to read its link quality.
+    pub const fn radio(&self) -> &Rui3Radio<C> {
+        &self.radio
+    }
+
+    /// Stops scheduling and returns the radio, in the state it is in.
+    pub fn release(self) -> Rui3Radio<C> {
+        self.radio
+    }
+
+    /// Returns the number of packets waiting to be sent.
+    pub const fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Queues a packet, sent by [`poll`] once the module is free.
+    ///
+    /// [`poll`]: Scheduler::poll
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::ParamError`] if `data` is empty or too long, or
+    /// [`Rui3Error::QueueFull`] if `N` packets are already waiting.
+    pub fn queue(&mut self, data: &[u8]) -> Result<(), Rui3Error> {
+        if data.is_empty() {
+            return Err(Rui3Error::ParamError);
+        }
+        let payload = at::urc::Payload::from_slice(data).map_err(|()| Rui3Error::ParamError)?;
+        self.queue
+            .push_back(payload)
+            .map_err(|_| Rui3Error::QueueFull)
+    }
+
+    /// Listens in continuous mode whenever the module is not transmitting.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Rui3Error`] if the module rejects the command or the AT client
+    /// fails. RX is then enabled at the next [`poll`].
+    ///
+    /// [`poll`]: Scheduler::poll
+    pub fn listen(&mut self) -> Result<(), nb::Error<Rui3Error>> {
+        self.listening = true;
+        if self.radio.state() == RadioState::Idle {
+            self.resume()?;
+        }
+        Ok(())
+    }
+
+    /// Stops listening, after the transmission or the window in progress.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`Rui3Error`] if the module rejects the command or the AT client
+    /// fails.
+    pub fn stop_listening(&mut self) -> Result<(), nb::Error<Rui3Error>> {
+        self.listening = false;
+        if self.radio.state() == RadioState::Rx {
+            self.radio
+                .set_receiving_window(at::commands::p2p::ReceiveWindow::StopListening)?;
+        }
+        Ok(())
+    }
+
+    /// Receives during a window of `millis` milliseconds, then goes back to
+    /// listening or idle.
+    ///
+    /// Queued packets are sent after the window.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Rui3Error::BusyError`] if a packet is being sent or a window is open,
+    /// [`Rui3Error::ParamError`] if `millis` is 0 or above 65533, or another
+    /// [`Rui3Error`] if the module rejects a command or the AT client fails.
+    pub fn receive_window<CLK>(
+        &mut self,
+        millis: u16,
+        clock: &mut CLK,
+    ) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        if matches!(self.radio.state(), RadioState::Tx | RadioState::RxWindow) {
+            return Err(nb::Error::Other(Rui3Error::BusyError));
+        }
+        // 0, 65534 and 65535 have special meanings.
+        if millis == 0 || millis > 65_533 {
+            return Err(nb::Error::Other(Rui3Error::ParamError));
+        }
+
+        let result = self
+            .radio
+            .set_receiving_window(at::commands::p2p::ReceiveWindow::Milliseconds(millis));
+        if let Err(error) = result {
+            // The first error is the one worth reporting.
+            let _ = self.resume();
+            return Err(error);
+        }
+
+        let timeout =
+            fugit::MillisDurationU32::millis(u32::from(millis)) + self.radio.tx_timeout_margin;
+        self.window_end = Some(clock.now() + timeout.convert());
+        Ok(())
+    }
+
+    /// Moves the state machine forward and returns the next received packet.
+    ///
+    /// Handles the events of the module in order: finishes the transmission in
+    /// progress and returns a received packet if there is one, even if it arrives
+    /// before the end of the transmission. Otherwise enables RX again if it should
+    /// be, and starts sending the next queued packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`nb::Error::WouldBlock`] if no packet has been received yet,
+    /// [`Rui3Error::TxTimeout`] if a transmission is not reported in time,
+    /// [`Rui3Error::ChannelBusy`] if channel activity detection found the channel
+    /// busy, or another [`Rui3Error`] if the module rejects a command or the AT
+    /// client fails. The packet being sent is dropped on errors.
+    pub fn poll<CLK>(
+        &mut self,
+        clock: &mut CLK,
+    ) -> Result<ReceivedPacket<TIMER_HZ>, nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        while let Some(urc) = self.radio.next_urc() {
+            match urc {
+                at::urc::URCMessages::PeerToPeerMessage { rssi, snr, data } => {
+                    return Ok(self.radio.record_packet(rssi, snr, data, clock));
+                }
+                at::urc::URCMessages::PeerToPeerTxDone if self.pending.is_some() => {
+                    self.end_transmission(Ok(()))?;
+                }
+                at::urc::URCMessages::CadDetected if self.pending.is_some() => {
+                    self.end_transmission(Err(nb::Error::Other(Rui3Error::ChannelBusy)))?;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(pending) = self.pending {
+            if clock.now() < pending.deadline {
+                return Err(nb::Error::WouldBlock);
+            }
+            let timeout = self.radio.abandon_transmission();
+            self.end_transmission(Err(timeout))?;
+        }
+
+        // The window is over once reported, or once elapsed if the event was missed.
+        if self.window_end.is_some_and(|end| clock.now() >= end) {
+            self.radio.close_rx_window();
+        }
+        if self.radio.state() != RadioState::RxWindow {
+            self.window_end = None;
+        }
+
+        // e.g. after a command failed, once the late events are consumed.
+        if self.radio.state() == RadioState::Idle && self.listening {
+            self.resume()?;
+        }
+
+        if matches!(self.radio.state(), RadioState::Idle | RadioState::Rx) {
+            self.start_transmission(clock)?;
+        }
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Starts sending the next queued packet, if any.
+    fn start_transmission<CLK>(&mut self, clock: &mut CLK) -> Result<(), nb::Error<Rui3Error>>
+    where
+        CLK: atat::clock::Clock<TIMER_HZ>,
+    {
+        let Some(payload) = self.queue.pop_front() else {
+            return Ok(());
+        };
+        match self.radio.start_send(&payload, clock) {
+            Ok(pending) => {
+                self.pending = Some(pending);
+                Ok(())
+            }
+            Err(error) => {
+                // RX may be stopped already.
+                let _ = self.resume();
+                Err(error)
+            }
+        }
+    }
+
+    /// Forgets the transmission in progress, which ended with `result`, and enables
+    /// RX again.
+    fn end_transmission(
+        &mut self,
+        result: Result<(), nb::Error<Rui3Error>>,
+    ) -> Result<(), nb::Error<Rui3Error>> {
+        self.pending = None;
+        let resumed = self.resume();
+        result.and(resumed)
+    }
+
+    /// Enables continuous RX if listening, from a module which is not transmitting.
+    fn resume(&mut self) -> Result<(), nb::Error<Rui3Error>> {
+        if !self.listening {
+            return Ok(());
+        }
+        // After a failure, the module may still be receiving.
+        self.radio.restart_continuous_rx()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockClock, MockModule, RxState};
+    use alloc::{string::String, vec};
+
+    fn scheduler() -> (MockModule, Scheduler<MockModule, 4, 1000>) {
+        let module = MockModule::new();
+        (module.clone(), Scheduler::new(Rui3Radio::new(module)))
+    }
+
+    #[test]
+    fn sends_queued_packets_between_receptions() {
+        let (module, mut scheduler) = scheduler();
+        let mut clock = MockClock::<1000>::new();
+        scheduler.listen().unwrap();
+        assert_eq!(scheduler.state(), RadioState::Rx);
+
+        scheduler.queue(&[1]).unwrap();
+        scheduler.queue(&[2]).unwrap();
+        assert_eq!(scheduler.poll(&mut clock), Err(nb::Error::WouldBlock));
+        assert_eq!(scheduler.state(), RadioState::Tx);
+        assert_eq!(module.rx_state(), RxState::Off);
+
+        module.inject_packet(-80, 5, &[9]);
+        // Ends the first transmission, then returns the packet.
+        let packet = scheduler.poll(&mut clock).unwrap();
+        assert_eq!(&packet.payload[..], &[9]);
+        assert_eq!(scheduler.state(), RadioState::Rx);
+        assert_eq!(module.rx_state(), RxState::Continuous);
+
+        while scheduler.queued() > 0 || scheduler.state() == RadioState::Tx {
+            assert_eq!(scheduler.poll(&mut clock), Err(nb::Error::WouldBlock));
+        }
+        assert_eq!(module.transmitted(), vec![vec![1], vec![2]]);
+        assert_eq!(module.rx_state(), RxState::Continuous);
+    }
+
+    #[test]
+    fn rejects_packets_when_the_queue_is_full() {
+        let (_, mut scheduler) = scheduler();
+        for byte in 0..4 {
+            scheduler.queue(&[byte]).unwrap();
+        }
+        assert_eq!(scheduler.queue(&[4]), Err(Rui3Error::QueueFull));
+        assert_eq!(scheduler.queue(&[]), Err(Rui3Error::ParamError));
+        assert_eq!(scheduler.queued(), 4);
+    }
+
+    #[test]
+    fn keeps_packets_received_before_the_end_of_the_transmission() {
+        let (module, mut scheduler) = scheduler();
+        let mut clock = MockClock::<1000>::new();
+        scheduler.listen().unwrap();
+        module.set_auto_tx_done(false);
+        scheduler.queue(&[1]).unwrap();
+        assert_eq!(scheduler.poll(&mut clock), Err(nb::Error::WouldBlock));
+        assert_eq!(scheduler.state(), RadioState::Tx);
+
+        module.inject_packet(-80, 5, &[9]);
+        module.inject_event("+EVT:TXP2P DONE");
+        let packet = scheduler.poll(&mut clock).unwrap();
+        assert_eq!(&packet.payload[..], &[9]);
+        assert_eq!(scheduler.state(), RadioState::Tx);
+        assert_eq!(scheduler.poll(&mut clock), Err(nb::Error::WouldBlock));
+        assert_eq!(scheduler.state(), RadioState::Rx);
+        assert_eq!(scheduler.radio().state(), RadioState::Rx);
+        assert_eq!(module.rx_state(), RxState::Continuous);
+    }
+
+    #[test]
+    fn resumes_rx_after_failed_commands() {
+        let (module, mut scheduler) = scheduler();
+        let mut clock = MockClock::<1000>::new();
+        scheduler.listen().unwrap();
+
+        module.fail_next("+PSEND", &Rui3Error::BusyError);
+        scheduler.queue(&[1]).unwrap();
+        assert_eq!(
+            scheduler.poll(&mut clock),
+            Err(nb::Error::Other(Rui3Error::BusyError))
+        );
+        assert_eq!(scheduler.state(), RadioState::Rx);
+        assert_eq!(module.rx_state(), RxState::Continuous);
+
+        // Enabling RX fails too: it is tried again at the next poll.
+        module.fail_next("+PSEND", &Rui3Error::BusyError);
+        module.fail_next("+PRECV=65534", &Rui3Error::BusyError);
+        scheduler.queue(&[2]).unwrap();
+        assert_eq!(
+            scheduler.poll(&mut clock),
+            Err(nb::Error::Other(Rui3Error::BusyError))
+        );
+        assert_eq!(scheduler.state(), RadioState::Idle);
+        assert_eq!(scheduler.poll(&mut clock), Err(nb::Error::WouldBlock));
+        assert_eq!(scheduler.state(), RadioState::Rx);
+        assert_eq!(module.rx_state(), RxState::Continuous);
+        assert!(module.transmitted().is_empty());
+    }
+
+    #[test]
+    fn resumes_rx_after_transmission_timeouts() {
+        let (module, mut scheduler) = scheduler();
+        let mut clock = MockClock::<1000>::new();
+        scheduler.listen().unwrap();
+        module.set_auto_tx_done(false);
+        scheduler.queue(&[1]).unwrap();
+
+        let error = loop {
+            match scheduler.poll(&mut clock) {
+                Err(nb::Error::WouldBlock) => {}
+                result => break result,
+            }
+        };
+        assert_eq!(error, Err(nb::Error::Other(Rui3Error::TxTimeout)));
+        // The module is still busy, RX is enabled once it is done.
+        assert_eq!(scheduler.state(), RadioState::Idle);
+        module.inject_event("+EVT:TXP2P DONE");
+        assert_eq!(scheduler.poll(&mut clock), Err(nb::Error::WouldBlock));
+        assert_eq!(scheduler.state(), RadioState::Rx);
+    }
+
+    #[test]
+    fn sends_after_the_receive_window() {
+        let (module, mut scheduler) = scheduler();
+        let mut clock = MockClock::<1000>::new();
+        scheduler.receive_window(500, &mut clock).unwrap();
+        assert_eq!(module.rx_state(), RxState::Window(500));
+        scheduler.queue(&[1]).unwrap();
+        assert_eq!(
+            scheduler.receive_window(500, &mut clock),
+            Err(nb::Error::Other(Rui3Error::BusyError))
+        );
+
+        // The module reports the end of the window, then the packet is sent.
+        module.inject_event("+EVT:RXP2P RECEIVE TIMEOUT");
+        assert_eq!(scheduler.poll(&mut clock), Err(nb::Error::WouldBlock));
+        assert_eq!(scheduler.state(), RadioState::Tx);
+        assert_eq!(
+            module.commands().last().map(String::as_str),
+            Some("+PSEND=01")
+        );
+    }
+}
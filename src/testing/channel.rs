@@ -0,0 +1,474 @@
+//! A simulated radio channel connecting [`MockModule`]s.
+
+use alloc::{
+    collections::BTreeMap,
+    rc::{Rc, Weak},
+    string::String,
+    vec::Vec,
+};
+use core::cell::RefCell;
+
+use super::{packet_event, MockModule, State};
+
+/// How packets travel from one module to another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkProfile {
+    /// Percentage of packets lost, from 0 to 100.
+    pub loss_percent: u8,
+    /// RSSI reported by the receiver, in dBm.
+    pub rssi: i16,
+    /// SNR reported by the receiver, in dB.
+    pub snr: i16,
+    /// Time between the transmission and the reception.
+    pub latency: fugit::MillisDurationU32,
+}
+
+impl Default for LinkProfile {
+    fn default() -> Self {
+        Self {
+            loss_percent: 0,
+            rssi: -60,
+            snr: 10,
+            latency: fugit::MillisDurationU32::millis(0),
+        }
+    }
+}
+
+/// A packet on the air.
+struct InFlight {
+    /// Channel time at which the packet is received.
+    arrival: u32,
+    receiver: usize,
+    event: String,
+}
+
+/// State shared by the handles of a [`Channel`].
+struct ChannelState {
+    modules: Vec<Weak<RefCell<State>>>,
+    profile: LinkProfile,
+    /// Profiles of specific links, by transmitter and receiver.
+    links: BTreeMap<(usize, usize), LinkProfile>,
+    in_flight: Vec<InFlight>,
+    /// Elapsed time, in milliseconds.
+    now: u32,
+    /// State of the generator deciding which packets are lost.
+    seed: u32,
+}
+
+/// Connection of a [`MockModule`] to a [`Channel`].
+pub(super) struct Attachment {
+    channel: Channel,
+    id: usize,
+}
+
+impl Attachment {
+    /// Sends a packet to the other modules of the channel.
+    pub(super) fn transmit(&self, settings: &[String], payload: &[u8]) {
+        self.channel.transmit(self.id, settings, payload);
+    }
+
+    /// Returns whether a packet is travelling towards the module.
+    pub(super) fn is_busy(&self) -> bool {
+        self.channel
+            .state
+            .borrow()
+            .in_flight
+            .iter()
+            .any(|packet| packet.receiver == self.id)
+    }
+}
+
+/// A simulated radio channel, connecting several [`MockModule`]s in-process.
+///
+/// A packet sent with `AT+PSEND` by one module is received as a `+EVT:RXP2P` event
+/// by the other modules which are listening with the same working mode, frequency,
+/// spreading factor, bandwidth, sync word, IQ inversion and encryption key.
+/// Packets can be lost, delayed, and received with any RSSI and SNR, see
+/// [`LinkProfile`].
+///
+/// ```
+/// # #[cfg(feature = "testing")] {
+/// use rui3_at::testing::{Channel, MockClock};
+///
+/// let channel = Channel::new();
+/// let mut car = rui3_at::Rui3Radio::new(channel.attach());
+/// let mut pit = rui3_at::Rui3Radio::new(channel.attach());
+/// let mut clock = MockClock::<1000>::new();
+///
+/// pit.set_receiving_window(rui3_at::at::commands::p2p::ReceiveWindow::Continuous)
+///     .unwrap();
+/// car.send(b"lap 12", &mut clock).unwrap();
+/// assert_eq!(&pit.poll(&mut clock).unwrap().payload[..], b"lap 12");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Channel {
+    state: Rc<RefCell<ChannelState>>,
+}
+
+impl Channel {
+    /// Creates a channel where every packet is received right away.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            state: Rc::new(RefCell::new(ChannelState {
+                modules: Vec::new(),
+                profile: LinkProfile::default(),
+                links: BTreeMap::new(),
+                in_flight: Vec::new(),
+                now: 0,
+                seed: 0x2545_F491,
+            })),
+        }
+    }
+
+    /// Creates a module connected to the channel.
+    #[must_use]
+    pub fn attach(&self) -> MockModule {
+        let module = MockModule::new();
+        let mut state = self.state.borrow_mut();
+        module.state.borrow_mut().channel = Some(Attachment {
+            channel: self.clone(),
+            id: state.modules.len(),
+        });
+        state.modules.push(Rc::downgrade(&module.state));
+        module
+    }
+
+    /// Sets the profile of every link without a profile of its own.
+    pub fn set_profile(&self, profile: LinkProfile) {
+        self.state.borrow_mut().profile = profile;
+    }
+
+    /// Sets the profile of the link from `transmitter` to `receiver`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a module is not attached to this channel.
+    pub fn set_link_profile(
+        &self,
+        transmitter: &MockModule,
+        receiver: &MockModule,
+        profile: LinkProfile,
+    ) {
+        let link = (self.id_of(transmitter), self.id_of(receiver));
+        self.state.borrow_mut().links.insert(link, profile);
+    }
+
+    /// Seeds the generator deciding which packets are lost.
+    pub fn set_seed(&self, seed: u32) {
+        // Xorshift never leaves zero.
+        self.state.borrow_mut().seed = seed.max(1);
+    }
+
+    /// Moves time forward, delivering the packets which arrive meanwhile.
+    pub fn advance(&self, duration: fugit::MillisDurationU32) {
+        let mut state = self.state.borrow_mut();
+        state.now = state.now.wrapping_add(duration.ticks());
+        let now = state.now;
+        let (arrived, in_flight) = core::mem::take(&mut state.in_flight)
+            .into_iter()
+            .partition(|packet| packet.arrival <= now);
+        state.in_flight = in_flight;
+        for packet in arrived {
+            state.deliver(packet.receiver, packet.event);
+        }
+    }
+
+    /// Returns the number of packets sent but not received yet.
+    #[must_use]
+    pub fn in_flight(&self) -> usize {
+        self.state.borrow().in_flight.len()
+    }
+
+    /// Returns the index of an attached module.
+    fn id_of(&self, module: &MockModule) -> usize {
+        match &module.state.borrow().channel {
+            Some(attachment) if Rc::ptr_eq(&attachment.channel.state, &self.state) => attachment.id,
+            _ => panic!("module not attached to this channel"),
+        }
+    }
+
+    /// Sends a packet from a module to the others with the same settings.
+    fn transmit(&self, transmitter: usize, settings: &[String], payload: &[u8]) {
+        let mut state = self.state.borrow_mut();
+        for receiver in 0..state.modules.len() {
+            if receiver == transmitter {
+                continue;
+            }
+            let Some(module) = state.modules[receiver].upgrade() else {
+                continue;
+            };
+            if module.borrow().air_settings() != settings {
+                continue;
+            }
+
+            let profile = state
+                .links
+                .get(&(transmitter, receiver))
+                .copied()
+                .unwrap_or(state.profile);
+            if state.next_random() % 100 < u32::from(profile.loss_percent) {
+                continue;
+            }
+            let event = packet_event(profile.rssi, profile.snr, payload);
+            if profile.latency.ticks() == 0 {
+                state.deliver(receiver, event);
+            } else {
+                let arrival = state.now.wrapping_add(profile.latency.ticks());
+                state.in_flight.push(InFlight {
+                    arrival,
+                    receiver,
+                    event,
+                });
+            }
+        }
+    }
+}
+
+impl Default for Channel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChannelState {
+    /// Hands a packet to a module.
+    fn deliver(&self, receiver: usize, event: String) {
+        if let Some(module) = self.modules[receiver].upgrade() {
+            module.borrow_mut().deliver(event);
+        }
+    }
+
+    /// Returns the next number of a xorshift generator.
+    const fn next_random(&mut self) -> u32 {
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        at::commands::p2p::ReceiveWindow,
+        testing::{MockClock, RxState},
+        Rui3Radio,
+    };
+
+    fn listen(radio: &mut Rui3Radio<MockModule>) {
+        radio
+            .set_receiving_window(ReceiveWindow::Continuous)
+            .unwrap();
+    }
+
+    #[test]
+    fn delivers_packets_to_listening_modules() {
+        let channel = Channel::new();
+        let mut car = Rui3Radio::new(channel.attach());
+        let pit_module = channel.attach();
+        let mut pit = Rui3Radio::new(pit_module.clone());
+        let idle = channel.attach();
+        let mut clock = MockClock::<1000>::new();
+
+        listen(&mut pit);
+        car.send(&[1, 2, 3], &mut clock).unwrap();
+
+        let packet = pit.poll(&mut clock).unwrap();
+        assert_eq!(&packet.payload[..], &[1, 2, 3]);
+        assert_eq!((packet.rssi, packet.snr), (-60, 10));
+        // Modules which are not listening miss the packet.
+        assert!(idle.pending_events().is_empty());
+        assert_eq!(pit_module.rx_state(), RxState::Continuous);
+
+        // The link works both ways, `send` re-enables RX.
+        pit.send(&[4], &mut clock).unwrap();
+        assert_eq!(&car.poll(&mut clock).unwrap().payload[..], &[4]);
+    }
+
+    #[test]
+    fn requires_matching_settings() {
+        for (name, value) in [
+            ("+PFREQ", "915000000"),
+            ("+PSF", "9"),
+            ("+PBW", "1"),
+            ("+SYNCWORD", "1424"),
+            ("+IQINVER", "1"),
+            ("+ENCRY", "1"),
+            ("+NWM", "2"),
+        ] {
+            let channel = Channel::new();
+            let mut car = Rui3Radio::new(channel.attach());
+            let pit = channel.attach();
+            pit.set_parameter(name, value);
+            let mut pit = Rui3Radio::new(pit);
+            let mut clock = MockClock::<1000>::new();
+
+            listen(&mut pit);
+            car.send(&[1], &mut clock).unwrap();
+            assert_eq!(pit.poll(&mut clock), Err(nb::Error::WouldBlock), "{name}");
+        }
+    }
+
+    #[test]
+    fn delivers_fsk_packets_with_matching_bitrates() {
+        let channel = Channel::new();
+        let mut car = Rui3Radio::new(channel.attach());
+        let mut pit = Rui3Radio::new(channel.attach());
+        let mut clock = MockClock::<1000>::new();
+        let fsk = crate::Configuration {
+            working_mode: crate::at::commands::p2p::WorkingMode::FskP2P,
+            bandwidth: crate::at::commands::p2p::Bandwidth::Fsk(125_000),
+            fsk_bitrate: 9_600,
+            ..crate::Configuration::default()
+        };
+        car.configure(fsk.clone(), &mut clock).unwrap();
+        pit.configure(fsk.clone(), &mut clock).unwrap();
+
+        listen(&mut pit);
+        car.send(&[1, 2], &mut clock).unwrap();
+        assert_eq!(&pit.poll(&mut clock).unwrap().payload[..], &[1, 2]);
+
+        pit.configure(
+            crate::Configuration {
+                fsk_bitrate: 4_800,
+                ..fsk
+            },
+            &mut clock,
+        )
+        .unwrap();
+        car.send(&[3], &mut clock).unwrap();
+        assert_eq!(pit.poll(&mut clock), Err(nb::Error::WouldBlock));
+    }
+
+    #[test]
+    fn detects_packets_on_the_air() {
+        let channel = Channel::new();
+        channel.set_profile(LinkProfile {
+            latency: fugit::MillisDurationU32::millis(50),
+            ..LinkProfile::default()
+        });
+        let mut car = Rui3Radio::new(channel.attach());
+        let mut pit = Rui3Radio::new(channel.attach());
+        let mut clock = MockClock::<1000>::new();
+        pit.set_channel_activity_detection(true).unwrap();
+
+        car.send(&[1], &mut clock).unwrap();
+        assert_eq!(
+            pit.send(&[2], &mut clock),
+            Err(nb::Error::Other(crate::Rui3Error::ChannelBusy))
+        );
+        channel.advance(fugit::MillisDurationU32::millis(50));
+        pit.send(&[2], &mut clock).unwrap();
+    }
+
+    #[test]
+    fn requires_the_same_encryption_key() {
+        let channel = Channel::new();
+        let car_module = channel.attach();
+        let pit_module = channel.attach();
+        for module in [&car_module, &pit_module] {
+            module.set_parameter("+ENCRY", "1");
+        }
+        pit_module.set_parameter("+ENCKEY", "0123456789ABCDEF");
+        let mut car = Rui3Radio::new(car_module);
+        let mut pit = Rui3Radio::new(pit_module);
+        let mut clock = MockClock::<1000>::new();
+
+        listen(&mut pit);
+        car.send(&[1], &mut clock).unwrap();
+        assert_eq!(pit.poll(&mut clock), Err(nb::Error::WouldBlock));
+
+        car.set_encryption_key("0123456789ABCDEF".into()).unwrap();
+        car.send(&[2], &mut clock).unwrap();
+        assert_eq!(&pit.poll(&mut clock).unwrap().payload[..], &[2]);
+    }
+
+    #[test]
+    fn applies_link_profiles() {
+        let channel = Channel::new();
+        let car_module = channel.attach();
+        let pit_module = channel.attach();
+        channel.set_link_profile(
+            &car_module,
+            &pit_module,
+            LinkProfile {
+                rssi: -110,
+                snr: -7,
+                latency: fugit::MillisDurationU32::millis(20),
+                ..LinkProfile::default()
+            },
+        );
+        let (mut car, mut pit) = (Rui3Radio::new(car_module), Rui3Radio::new(pit_module));
+        let mut clock = MockClock::<1000>::new();
+
+        listen(&mut pit);
+        car.send(&[1], &mut clock).unwrap();
+        assert_eq!(channel.in_flight(), 1);
+        channel.advance(fugit::MillisDurationU32::millis(19));
+        assert_eq!(pit.poll(&mut clock), Err(nb::Error::WouldBlock));
+        channel.advance(fugit::MillisDurationU32::millis(1));
+        let packet = pit.poll(&mut clock).unwrap();
+        assert_eq!((packet.rssi, packet.snr), (-110, -7));
+    }
+
+    #[test]
+    fn loses_packets() {
+        let channel = Channel::new();
+        let mut car = Rui3Radio::new(channel.attach());
+        let mut pit = Rui3Radio::new(channel.attach());
+        let mut clock = MockClock::<1000>::new();
+        channel.set_seed(7);
+        channel.set_profile(LinkProfile {
+            loss_percent: 30,
+            ..LinkProfile::default()
+        });
+
+        listen(&mut pit);
+        let mut received = 0;
+        for i in 0..200 {
+            car.send(&[i], &mut clock).unwrap();
+            if pit.poll(&mut clock).is_ok() {
+                received += 1;
+            }
+        }
+        assert!((110..=170).contains(&received), "{received}");
+
+        channel.set_profile(LinkProfile {
+            loss_percent: 100,
+            ..LinkProfile::default()
+        });
+        car.send(&[1], &mut clock).unwrap();
+        assert_eq!(pit.poll(&mut clock), Err(nb::Error::WouldBlock));
+    }
+
+    #[test]
+    fn receive_windows_close() {
+        let channel = Channel::new();
+        let mut car = Rui3Radio::new(channel.attach());
+        let pit_module = channel.attach();
+        let mut pit = Rui3Radio::new(pit_module.clone());
+        let mut clock = MockClock::<1000>::new();
+
+        pit.set_receiving_window(ReceiveWindow::OnePacket).unwrap();
+        car.send(&[1], &mut clock).unwrap();
+        car.send(&[2], &mut clock).unwrap();
+        assert_eq!(&pit.poll(&mut clock).unwrap().payload[..], &[1]);
+        assert_eq!(pit.poll(&mut clock), Err(nb::Error::WouldBlock));
+        assert_eq!(pit_module.rx_state(), RxState::Off);
+
+        pit.set_receiving_window(ReceiveWindow::Milliseconds(100))
+            .unwrap();
+        car.send(&[3], &mut clock).unwrap();
+        assert_eq!(&pit.poll(&mut clock).unwrap().payload[..], &[3]);
+        // Reading the end of the window.
+        assert_eq!(pit.poll(&mut clock), Err(nb::Error::WouldBlock));
+        assert_eq!(pit_module.rx_state(), RxState::Off);
+        car.send(&[4], &mut clock).unwrap();
+        assert!(pit_module.pending_events().is_empty());
+    }
+}
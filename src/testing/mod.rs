@@ -0,0 +1,831 @@
+//! A simulated RUI3 module, to test code using [`Rui3Radio`] without hardware.
+//!
+//! [`MockModule`] implements [`atat::AtatClient`]: it answers the AT commands sent by
+//! [`Rui3Radio`] like a RUI3 module in P2P mode would, with the same status lines, and
+//! queues the `+EVT` events the module would send. Tests can script errors and inject
+//! events with the handle kept on the side.
+//!
+//! A [`Channel`] connects several modules, to test both ends of a link.
+//!
+//! ```
+//! # #[cfg(feature = "testing")] {
+//! use rui3_at::testing::{MockClock, MockModule};
+//!
+//! let module = MockModule::new();
+//! let mut radio = rui3_at::Rui3Radio::new(module.clone());
+//! let mut clock = MockClock::<1000>::new();
+//!
+//! module.inject_packet(-40, 7, &[1, 2, 3]);
+//! let packet = radio.receive(&mut clock).unwrap();
+//! assert_eq!(&packet.payload[..], &[1, 2, 3]);
+//!
+//! radio.send(&[4, 5], &mut clock).unwrap();
+//! assert_eq!(module.transmitted(), vec![vec![4, 5]]);
+//! # }
+//! ```
+//!
+//! [`Rui3Radio`]: crate::Rui3Radio
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{cell::RefCell, fmt::Write};
+
+use crate::Rui3Error;
+
+mod channel;
+
+pub use channel::{Channel, LinkProfile};
+
+/// Receiving state of the simulated module, as set with `AT+PRECV`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RxState {
+    /// RX is disabled.
+    Off,
+    /// RX is enabled for the given number of milliseconds.
+    Window(u16),
+    /// RX is enabled until one packet is received.
+    OnePacket,
+    /// RX is enabled until `AT+PRECV=0`.
+    Continuous,
+}
+
+/// How a parameter set with `AT+<NAME>=<value>` is validated.
+#[derive(Clone, Copy)]
+enum Rule {
+    /// A decimal number in the range.
+    Range(u32, u32),
+    /// Exactly this many hex digits.
+    Hex(usize),
+    /// A `LoRaWAN` class.
+    Class,
+    /// Up to this many characters.
+    Text(usize),
+    /// Cannot be set.
+    ReadOnly,
+}
+
+/// Parameters of the module, their default value and validation rule.
+const PARAMETERS: [(&str, &str, Rule); 35] = [
+    ("+NWM", "0", Rule::Range(0, 2)),
+    ("+PFREQ", "868000000", Rule::Range(150_000_000, 960_000_000)),
+    ("+PSF", "7", Rule::Range(5, 12)),
+    ("+PBW", "0", Rule::Range(0, 467_000)),
+    ("+PCR", "0", Rule::Range(0, 3)),
+    ("+PPL", "8", Rule::Range(2, 65_535)),
+    ("+PTP", "14", Rule::Range(5, 22)),
+    ("+ENCRY", "0", Rule::Range(0, 1)),
+    ("+ENCKEY", "0000000000000000", Rule::Hex(16)),
+    ("+IQINVER", "0", Rule::Range(0, 1)),
+    ("+SYNCWORD", "3444", Rule::Hex(4)),
+    ("+SYMBOLTIMEOUT", "0", Rule::Range(0, 248)),
+    ("+NJM", "1", Rule::Range(0, 1)),
+    ("+NJS", "0", Rule::ReadOnly),
+    ("+DEVEUI", "0000000000000000", Rule::Hex(16)),
+    ("+APPEUI", "0000000000000000", Rule::Hex(16)),
+    ("+APPKEY", "00000000000000000000000000000000", Rule::Hex(32)),
+    ("+DEVADDR", "00000000", Rule::Hex(8)),
+    (
+        "+APPSKEY",
+        "00000000000000000000000000000000",
+        Rule::Hex(32),
+    ),
+    (
+        "+NWKSKEY",
+        "00000000000000000000000000000000",
+        Rule::Hex(32),
+    ),
+    ("+CFM", "0", Rule::Range(0, 1)),
+    ("+CFS", "0", Rule::ReadOnly),
+    ("+ADR", "0", Rule::Range(0, 1)),
+    ("+DR", "0", Rule::Range(0, 15)),
+    ("+BAND", "4", Rule::Range(0, 12)),
+    ("+MASK", "0001", Rule::Hex(4)),
+    ("+RETY", "0", Rule::Range(0, 7)),
+    ("+CLASS", "A", Rule::Class),
+    ("+ALIAS", "", Rule::Text(16)),
+    ("+SN", "MOCK0000000001", Rule::ReadOnly),
+    ("+VER", "RUI_4.0.0_MOCK", Rule::ReadOnly),
+    ("+HWMODEL", "mock", Rule::ReadOnly),
+    ("+PBR", "50000", Rule::Range(600, 300_000)),
+    ("+PFDEV", "5000", Rule::Range(600, 200_000)),
+    ("+CAD", "0", Rule::Range(0, 1)),
+];
+
+/// Number of commands the module leaves unanswered while it restarts after a change
+/// of working mode.
+const BOOT_COMMANDS: usize = 2;
+
+/// State shared by the handles of a [`MockModule`].
+struct State {
+    parameters: BTreeMap<String, String>,
+    rx: RxState,
+    transmitting: bool,
+    auto_tx_done: bool,
+    events: VecDeque<String>,
+    commands: Vec<String>,
+    transmitted: Vec<Vec<u8>>,
+    failures: Vec<(String, String)>,
+    /// Commands answered with `AT_COMMAND_NOT_FOUND`, as on older firmware.
+    unsupported: Vec<String>,
+    /// Channel the module transmits on, if attached to one.
+    channel: Option<channel::Attachment>,
+    /// Number of upcoming channel activity detections which find the channel busy.
+    busy_detections: usize,
+    /// Number of upcoming join attempts which fail.
+    join_failures: usize,
+    /// Whether the network acknowledges confirmed uplinks.
+    uplink_acknowledged: bool,
+    /// Number of upcoming events reported by the module which are lost.
+    lost_events: usize,
+    /// Number of upcoming commands left unanswered while the module restarts.
+    booting: usize,
+}
+
+/// Answer of the module to a command.
+enum Reply {
+    /// `OK`, with the value of a query.
+    Ok(String),
+    /// An error status line.
+    Error(String),
+    /// Nothing, the client times out.
+    NoAnswer,
+}
+
+/// A simulated RUI3 module.
+///
+/// Clones are handles to the same module: give one to [`Rui3Radio::new`] and keep
+/// another one to script the module and inspect what it received.
+///
+/// Query responses are handed to the commands as the value only, e.g. `868000000`
+/// for `AT+PFREQ=?`, and errors as the status line, e.g. `AT_PARAM_ERROR`, like the
+/// digester configured with [`custom_error`] does.
+///
+/// Changing the working mode with `AT+NWM` restarts the module, which leaves the
+/// next commands unanswered while it boots.
+///
+/// [`Rui3Radio::new`]: crate::Rui3Radio::new
+/// [`custom_error`]: crate::error::custom_error
+#[derive(Clone)]
+pub struct MockModule {
+    state: Rc<RefCell<State>>,
+}
+
+impl MockModule {
+    /// Creates a module with the default RUI3 parameters, in `LoRa` P2P mode.
+    #[must_use]
+    pub fn new() -> Self {
+        let parameters = PARAMETERS
+            .iter()
+            .map(|(name, value, _)| ((*name).to_string(), (*value).to_string()))
+            .collect();
+        Self {
+            state: Rc::new(RefCell::new(State {
+                parameters,
+                rx: RxState::Off,
+                transmitting: false,
+                auto_tx_done: true,
+                events: VecDeque::new(),
+                commands: Vec::new(),
+                transmitted: Vec::new(),
+                failures: Vec::new(),
+                unsupported: Vec::new(),
+                channel: None,
+                busy_detections: 0,
+                join_failures: 0,
+                uplink_acknowledged: true,
+                lost_events: 0,
+                booting: 0,
+            })),
+        }
+    }
+
+    /// Queues an event, e.g. `+EVT:RXP2P RECEIVE TIMEOUT`.
+    ///
+    /// The `+EVT:` prefix is optional.
+    pub fn inject_event(&self, event: &str) {
+        let event = event.strip_prefix("+EVT:").unwrap_or(event);
+        self.state.borrow_mut().events.push_back(event.to_string());
+    }
+
+    /// Queues the `+EVT:RXP2P` event of a received packet.
+    pub fn inject_packet(&self, rssi: i16, snr: i16, payload: &[u8]) {
+        self.inject_event(&packet_event(rssi, snr, payload));
+    }
+
+    /// Makes the next command starting with `command` (e.g. `+PSEND`) fail with
+    /// the status line of `error`.
+    pub fn fail_next(&self, command: &str, error: &Rui3Error) {
+        self.state
+            .borrow_mut()
+            .failures
+            .push((command.to_string(), error.to_string()));
+    }
+
+    /// Makes the module answer `AT_COMMAND_NOT_FOUND` to a command (e.g. `+P2P`),
+    /// like firmware which does not know it.
+    pub fn set_unsupported(&self, command: &str) {
+        self.state
+            .borrow_mut()
+            .unsupported
+            .push(command.to_string());
+    }
+
+    /// Sets whether the module reports `+EVT:TXP2P DONE` after a transmission.
+    ///
+    /// Enabled by default; disable it to test timeouts.
+    pub fn set_auto_tx_done(&self, enabled: bool) {
+        self.state.borrow_mut().auto_tx_done = enabled;
+    }
+
+    /// Makes the next `count` channel activity detections find the channel busy.
+    ///
+    /// With `AT+CAD=1`, `AT+PSEND` reports `+EVT:CAD DETECTED` and sends nothing when
+    /// the channel is busy, or `+EVT:CAD DONE` before sending. On a [`Channel`], the
+    /// channel is also busy while a packet is travelling towards the module.
+    pub fn set_busy_detections(&self, count: usize) {
+        self.state.borrow_mut().busy_detections = count;
+    }
+
+    /// Makes the next `count` join attempts fail with `+EVT:JOIN_FAILED_RX_TIMEOUT`.
+    pub fn set_join_failures(&self, count: usize) {
+        self.state.borrow_mut().join_failures = count;
+    }
+
+    /// Sets whether the network acknowledges confirmed uplinks.
+    ///
+    /// Enabled by default; disable it to get `+EVT:SEND_CONFIRMED_FAILED`.
+    pub fn set_uplink_acknowledged(&self, acknowledged: bool) {
+        self.state.borrow_mut().uplink_acknowledged = acknowledged;
+    }
+
+    /// Drops the next `count` events reported by the module in answer to a command,
+    /// such as `+EVT:TXP2P DONE` or `+EVT:JOINED`, as if the serial link lost them.
+    pub fn set_lost_events(&self, count: usize) {
+        self.state.borrow_mut().lost_events = count;
+    }
+
+    /// Returns the value of a parameter, e.g. `+PFREQ`.
+    #[must_use]
+    pub fn parameter(&self, name: &str) -> Option<String> {
+        self.state.borrow().parameters.get(name).cloned()
+    }
+
+    /// Sets the value of a parameter, bypassing validation.
+    pub fn set_parameter(&self, name: &str, value: &str) {
+        self.state
+            .borrow_mut()
+            .parameters
+            .insert(name.to_string(), value.to_string());
+    }
+
+    /// Returns the receiving state.
+    #[must_use]
+    pub fn rx_state(&self) -> RxState {
+        self.state.borrow().rx
+    }
+
+    /// Returns whether a transmission is in progress.
+    #[must_use]
+    pub fn is_transmitting(&self) -> bool {
+        self.state.borrow().transmitting
+    }
+
+    /// Returns every command received, without `AT` and line end, e.g. `+PSF=7`.
+    #[must_use]
+    pub fn commands(&self) -> Vec<String> {
+        self.state.borrow().commands.clone()
+    }
+
+    /// Forgets the commands received so far.
+    pub fn clear_commands(&self) {
+        self.state.borrow_mut().commands.clear();
+    }
+
+    /// Returns every payload sent with `AT+PSEND`.
+    #[must_use]
+    pub fn transmitted(&self) -> Vec<Vec<u8>> {
+        self.state.borrow().transmitted.clone()
+    }
+
+    /// Returns the events not read yet.
+    #[must_use]
+    pub fn pending_events(&self) -> Vec<String> {
+        self.state.borrow().events.iter().cloned().collect()
+    }
+
+    /// Handles a command line and returns the answer of the module.
+    fn handle(&self, line: &str) -> Reply {
+        let mut state = self.state.borrow_mut();
+        let command = line.trim().strip_prefix("AT").unwrap_or(line).to_string();
+        state.commands.push(command.clone());
+        if state.booting > 0 {
+            state.booting -= 1;
+            return Reply::NoAnswer;
+        }
+
+        if let Some(index) = state
+            .failures
+            .iter()
+            .position(|(prefix, _)| command.starts_with(prefix.as_str()))
+        {
+            return Reply::Error(state.failures.remove(index).1);
+        }
+
+        let (name, argument) = match command.split_once('=') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (command.as_str(), None),
+        };
+        if state
+            .unsupported
+            .iter()
+            .any(|unsupported| unsupported == name)
+        {
+            return Reply::Error(Rui3Error::CommandNotFound.to_string());
+        }
+        match (name, argument) {
+            // `AT`, `ATZ` and `ATR`.
+            ("", None) => Reply::Ok(String::new()),
+            ("Z", None) => {
+                // Nothing survives a restart but the parameters.
+                state.rx = RxState::Off;
+                state.transmitting = false;
+                state.events.clear();
+                Reply::Ok(String::new())
+            }
+            ("R", None) => {
+                drop(state);
+                let defaults = Self::new();
+                self.state.borrow_mut().parameters = defaults.state.borrow().parameters.clone();
+                Reply::Ok(String::new())
+            }
+            ("+PSEND", Some(payload)) => state.transmit(payload),
+            ("+PRECV", Some(window)) => state.receive(window),
+            ("+JOIN", Some(arguments)) => state.join(arguments),
+            ("+SEND", Some(_)) => state.send_uplink(),
+            ("+P2P", Some("?")) => {
+                let value = ["+PFREQ", "+PSF", "+PBW", "+PCR", "+PPL", "+PTP"]
+                    .iter()
+                    .map(|name| state.parameters[*name].as_str())
+                    .collect::<Vec<_>>()
+                    .join(":");
+                Reply::Ok(value)
+            }
+            ("+P2P", Some(values)) => state.set_p2p(values),
+            ("+NWM", Some(mode)) if mode != "?" => state.set_working_mode(mode),
+            (name, Some("?")) => state.parameters.get(name).map_or_else(
+                || Reply::Error(Rui3Error::CommandNotFound.to_string()),
+                |value| Reply::Ok(value.clone()),
+            ),
+            (name, Some(value)) => state.set(name, value),
+            _ => Reply::Error(Rui3Error::CommandNotFound.to_string()),
+        }
+    }
+
+    /// Pops the next event if `f` accepts it.
+    fn pop_event<URC, F>(&self, f: F)
+    where
+        URC: atat::AtatUrc,
+        F: FnOnce(URC::Response) -> bool,
+    {
+        let Some(event) = self.state.borrow_mut().events.pop_front() else {
+            return;
+        };
+        // Events the URC type does not know are dropped, like the client does.
+        let Some(urc) = URC::parse(event.as_bytes()) else {
+            return;
+        };
+        if f(urc) {
+            let mut state = self.state.borrow_mut();
+            if event == "TXP2P DONE" {
+                state.transmitting = false;
+            } else if event == "RXP2P RECEIVE TIMEOUT" && matches!(state.rx, RxState::Window(_)) {
+                state.rx = RxState::Off;
+            }
+        } else {
+            self.state.borrow_mut().events.push_front(event);
+        }
+    }
+}
+
+impl Default for MockModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    /// Handles `AT+<name>=<value>`.
+    fn set(&mut self, name: &str, value: &str) -> Reply {
+        let Some((_, _, rule)) = PARAMETERS.iter().find(|(known, _, _)| *known == name) else {
+            return Reply::Error(Rui3Error::CommandNotFound.to_string());
+        };
+        let valid = match *rule {
+            Rule::Range(min, max) => value
+                .parse::<u32>()
+                .is_ok_and(|value| (min..=max).contains(&value)),
+            Rule::Hex(len) => value.len() == len && value.bytes().all(|b| b.is_ascii_hexdigit()),
+            Rule::Class => matches!(value, "A" | "B" | "C"),
+            Rule::Text(len) => value.len() <= len,
+            Rule::ReadOnly => false,
+        };
+        if !valid {
+            return Reply::Error(Rui3Error::ParamError.to_string());
+        }
+        self.parameters.insert(name.to_string(), value.to_string());
+        Reply::Ok(String::new())
+    }
+
+    /// Handles `AT+NWM=<mode>`, restarting the module if the mode changes.
+    fn set_working_mode(&mut self, mode: &str) -> Reply {
+        let changed = self.parameters["+NWM"] != mode;
+        let reply = self.set("+NWM", mode);
+        if changed && matches!(reply, Reply::Ok(_)) {
+            // Nothing survives a restart but the parameters.
+            self.rx = RxState::Off;
+            self.transmitting = false;
+            self.events.clear();
+            self.booting = BOOT_COMMANDS;
+        }
+        reply
+    }
+
+    /// Handles `AT+P2P=<frequency>:<sf>:<bandwidth>:<code rate>:<preamble>:<power>`,
+    /// setting every parameter or none.
+    fn set_p2p(&mut self, values: &str) -> Reply {
+        let names = ["+PFREQ", "+PSF", "+PBW", "+PCR", "+PPL", "+PTP"];
+        let values: Vec<&str> = values.split(':').collect();
+        if values.len() != names.len() {
+            return Reply::Error(Rui3Error::ParamError.to_string());
+        }
+        let previous = self.parameters.clone();
+        for (name, value) in names.iter().zip(values) {
+            if let Reply::Error(error) = self.set(name, value) {
+                self.parameters = previous;
+                return Reply::Error(error);
+            }
+        }
+        Reply::Ok(String::new())
+    }
+
+    /// Handles `AT+PSEND=<payload>`.
+    fn transmit(&mut self, payload: &str) -> Reply {
+        if self.parameters["+NWM"] == "1" {
+            return Reply::Error(Rui3Error::Error.to_string());
+        }
+        if self.transmitting || self.rx != RxState::Off {
+            return Reply::Error(Rui3Error::BusyError.to_string());
+        }
+        let Some(data) = decode_hex(payload) else {
+            return Reply::Error(Rui3Error::ParamError.to_string());
+        };
+        if data.is_empty() || data.len() > crate::at::urc::MAX_PAYLOAD_LEN {
+            return Reply::Error(Rui3Error::ParamError.to_string());
+        }
+
+        if self.parameters["+CAD"] == "1" {
+            let busy = if self.busy_detections > 0 {
+                self.busy_detections -= 1;
+                true
+            } else {
+                self.channel
+                    .as_ref()
+                    .is_some_and(channel::Attachment::is_busy)
+            };
+            if busy {
+                self.report("CAD DETECTED");
+                return Reply::Ok(String::new());
+            }
+            self.report("CAD DONE");
+        }
+
+        if let Some(attachment) = &self.channel {
+            attachment.transmit(&self.air_settings(), &data);
+        }
+        self.transmitted.push(data);
+        self.transmitting = true;
+        if self.auto_tx_done {
+            self.report("TXP2P DONE");
+        }
+        Reply::Ok(String::new())
+    }
+
+    /// Returns the parameters that must match for two modules to hear each other.
+    fn air_settings(&self) -> Vec<String> {
+        let modulation: &[&str] = if self.parameters["+NWM"] == "2" {
+            &["+PBR", "+PFDEV"]
+        } else {
+            &["+PSF"]
+        };
+        let mut settings: Vec<String> =
+            ["+NWM", "+PFREQ", "+PBW", "+SYNCWORD", "+IQINVER", "+ENCRY"]
+                .iter()
+                .chain(modulation)
+                .map(|name| self.parameters[*name].clone())
+                .collect();
+        if self.parameters["+ENCRY"] == "1" {
+            settings.push(self.parameters["+ENCKEY"].clone());
+        }
+        settings
+    }
+
+    /// Queues a packet received over the air, if RX is enabled.
+    fn deliver(&mut self, event: String) {
+        match self.rx {
+            RxState::Off => {}
+            RxState::OnePacket => {
+                self.rx = RxState::Off;
+                self.events.push_back(event);
+            }
+            RxState::Window(_) => {
+                // The packet arrives before the end of the window.
+                let index = self
+                    .events
+                    .iter()
+                    .rposition(|queued| queued == "RXP2P RECEIVE TIMEOUT")
+                    .unwrap_or(self.events.len());
+                self.events.insert(index, event);
+            }
+            RxState::Continuous => self.events.push_back(event),
+        }
+    }
+
+    /// Handles `AT+PRECV=<window>`.
+    fn receive(&mut self, window: &str) -> Reply {
+        let Ok(window) = window.parse::<u16>() else {
+            return Reply::Error(Rui3Error::ParamError.to_string());
+        };
+        if self.parameters["+NWM"] == "1" {
+            return Reply::Error(Rui3Error::Error.to_string());
+        }
+        if self.transmitting {
+            return Reply::Error(Rui3Error::BusyError.to_string());
+        }
+        // In continuous mode, only stopping RX is accepted.
+        if self.rx == RxState::Continuous && window != 0 {
+            return Reply::Error(Rui3Error::BusyError.to_string());
+        }
+
+        self.rx = match window {
+            0 => RxState::Off,
+            65_534 => RxState::Continuous,
+            65_535 => RxState::OnePacket,
+            millis => {
+                // The window ends after the packets already on the air.
+                self.report("RXP2P RECEIVE TIMEOUT");
+                RxState::Window(millis)
+            }
+        };
+        Reply::Ok(String::new())
+    }
+
+    /// Handles `AT+JOIN=...`, joining right away once the failing attempts are over.
+    fn join(&mut self, arguments: &str) -> Reply {
+        if self.parameters["+NWM"] != "1" {
+            return Reply::Error(Rui3Error::Error.to_string());
+        }
+        let Some(attempts) = arguments
+            .split(':')
+            .nth(3)
+            .and_then(|attempts| attempts.parse::<usize>().ok())
+        else {
+            return Reply::Error(Rui3Error::ParamError.to_string());
+        };
+        for _ in 0..attempts {
+            if self.join_failures == 0 {
+                self.parameters.insert("+NJS".to_string(), "1".to_string());
+                self.report("JOINED");
+                break;
+            }
+            self.join_failures -= 1;
+            self.report("JOIN_FAILED_RX_TIMEOUT");
+        }
+        Reply::Ok(String::new())
+    }
+
+    /// Handles `AT+SEND=...`, acknowledging confirmed uplinks.
+    fn send_uplink(&mut self) -> Reply {
+        if self.parameters["+NJS"] != "1" {
+            return Reply::Error(Rui3Error::NoNetworkJoined.to_string());
+        }
+        let event = if self.parameters["+CFM"] != "1" {
+            "TX_DONE"
+        } else if self.uplink_acknowledged {
+            "SEND_CONFIRMED_OK"
+        } else {
+            "SEND_CONFIRMED_FAILED"
+        };
+        self.report(event);
+        Reply::Ok(String::new())
+    }
+
+    /// Queues an event reported by the module, unless it is lost.
+    fn report(&mut self, event: &str) {
+        if self.lost_events > 0 {
+            self.lost_events -= 1;
+        } else {
+            self.events.push_back(event.to_string());
+        }
+    }
+}
+
+impl atat::AtatClient for MockModule {
+    fn send<A, const LEN: usize>(&mut self, cmd: &A) -> nb::Result<A::Response, atat::Error>
+    where
+        A: atat::AtatCmd<LEN>,
+    {
+        let bytes = cmd.as_bytes();
+        let line =
+            core::str::from_utf8(&bytes).map_err(|_| nb::Error::Other(atat::Error::Write))?;
+        let response = match self.handle(line) {
+            Reply::Ok(value) => cmd.parse(Ok(value.as_bytes())),
+            Reply::Error(line) => cmd.parse(Err(atat::InternalError::Custom(line.as_bytes()))),
+            Reply::NoAnswer => Err(atat::Error::Timeout),
+        };
+        response.map_err(nb::Error::Other)
+    }
+
+    fn peek_urc_with<URC, F>(&mut self, f: F)
+    where
+        URC: atat::AtatUrc,
+        F: FnOnce(URC::Response) -> bool,
+    {
+        self.pop_event::<URC, F>(f);
+    }
+
+    fn check_response<A, const LEN: usize>(
+        &mut self,
+        _cmd: &A,
+    ) -> nb::Result<A::Response, atat::Error>
+    where
+        A: atat::AtatCmd<LEN>,
+    {
+        // Commands are answered as soon as they are sent.
+        Err(nb::Error::WouldBlock)
+    }
+
+    fn get_mode(&self) -> atat::Mode {
+        atat::Mode::Blocking
+    }
+
+    fn reset(&mut self) {
+        self.state.borrow_mut().events.clear();
+    }
+}
+
+/// A clock for tests, which moves forward by one tick every time it is read.
+///
+/// Advancing on every read lets timeouts elapse without sleeping.
+#[derive(Clone, Debug, Default)]
+pub struct MockClock<const TIMER_HZ: u32> {
+    ticks: u32,
+    deadline: Option<u32>,
+}
+
+impl<const TIMER_HZ: u32> MockClock<TIMER_HZ> {
+    /// Creates a clock starting at zero.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            ticks: 0,
+            deadline: None,
+        }
+    }
+
+    /// Moves the clock forward.
+    pub const fn advance(&mut self, duration: fugit::TimerDurationU32<TIMER_HZ>) {
+        self.ticks = self.ticks.wrapping_add(duration.ticks());
+    }
+}
+
+impl<const TIMER_HZ: u32> atat::clock::Clock<TIMER_HZ> for MockClock<TIMER_HZ> {
+    type Error = core::convert::Infallible;
+
+    fn now(&mut self) -> fugit::TimerInstantU32<TIMER_HZ> {
+        let now = fugit::TimerInstantU32::from_ticks(self.ticks);
+        self.ticks = self.ticks.wrapping_add(1);
+        now
+    }
+
+    fn start(&mut self, duration: fugit::TimerDurationU32<TIMER_HZ>) -> Result<(), Self::Error> {
+        self.deadline = Some(self.ticks.wrapping_add(duration.ticks()));
+        Ok(())
+    }
+
+    fn cancel(&mut self) -> Result<(), Self::Error> {
+        self.deadline = None;
+        Ok(())
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Self::Error> {
+        let deadline = self.deadline;
+        match deadline {
+            Some(deadline) if self.now().ticks() < deadline => Err(nb::Error::WouldBlock),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Formats the `RXP2P` event of a received packet.
+fn packet_event(rssi: i16, snr: i16, payload: &[u8]) -> String {
+    let mut event = String::new();
+    // Writing to a `String` cannot fail.
+    let _ = write!(event, "RXP2P:{rssi}:{snr}:");
+    for b in payload {
+        let _ = write!(event, "{b:02X}");
+    }
+    event
+}
+
+/// Decodes the hex payload of `AT+PSEND`.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atat::AtatClient;
+
+    #[test]
+    fn answers_queries_and_validates_parameters() {
+        let mut module = MockModule::new();
+        assert_eq!(
+            module
+                .send(&crate::at::commands::p2p::GetP2PFrequency {})
+                .map(|r| r.frequency),
+            Ok(868_000_000)
+        );
+        assert_eq!(
+            module.handle("AT+PSF=13\r\n").error(),
+            Some(Rui3Error::ParamError.to_string())
+        );
+        assert_eq!(
+            module.handle("AT+NOPE=?\r\n").error(),
+            Some("AT_COMMAND_NOT_FOUND".into())
+        );
+        assert!(module.handle("AT+PSF=12\r\n").error().is_none());
+        assert_eq!(module.parameter("+PSF").as_deref(), Some("12"));
+    }
+
+    #[test]
+    fn reports_busy_while_transmitting() {
+        let module = MockModule::new();
+        assert!(module.handle("AT+PSEND=AABB").error().is_none());
+        assert!(module.is_transmitting());
+        assert_eq!(
+            module.handle("AT+PSEND=AABB").error(),
+            Some(Rui3Error::BusyError.to_string())
+        );
+
+        let mut client = module.clone();
+        assert_eq!(
+            client.check_urc::<crate::at::urc::URCMessages>(),
+            Some(crate::at::urc::URCMessages::PeerToPeerTxDone)
+        );
+        assert!(!module.is_transmitting());
+        assert_eq!(module.transmitted(), alloc::vec![alloc::vec![0xAA, 0xBB]]);
+    }
+
+    #[test]
+    fn restarts_when_the_working_mode_changes() {
+        let mut module = MockModule::new();
+        assert!(module.handle("AT+PRECV=65534").error().is_none());
+        assert!(module.handle("AT+NWM=0").error().is_none());
+        assert_eq!(module.rx_state(), RxState::Continuous);
+
+        assert!(module.handle("AT+NWM=2").error().is_none());
+        assert_eq!(module.rx_state(), RxState::Off);
+        for _ in 0..BOOT_COMMANDS {
+            assert_eq!(
+                module
+                    .send(&crate::at::commands::general::Attention {})
+                    .err(),
+                Some(nb::Error::Other(atat::Error::Timeout))
+            );
+        }
+        assert!(module
+            .send(&crate::at::commands::general::Attention {})
+            .is_ok());
+        assert_eq!(module.parameter("+NWM").as_deref(), Some("2"));
+    }
+
+    impl Reply {
+        fn error(self) -> Option<String> {
+            match self {
+                Self::Ok(_) => None,
+                Self::Error(line) => Some(line),
+                Self::NoAnswer => Some(String::new()),
+            }
+        }
+    }
+}